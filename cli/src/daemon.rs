@@ -0,0 +1,801 @@
+use crate::stream::{EventId, EventLog, Sequenced};
+use crate::{pick_interface, pick_profile, start_session, ScanArgs};
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures_util::stream::{self, Stream, StreamExt};
+use ndm_core::config::AppConfig;
+use ndm_core::export::{self, ExportFormat};
+use ndm_core::inventory;
+use ndm_core::logging;
+use ndm_core::models::{DeviceId, DeviceStatus, DeviceTable, NetworkDevice};
+use ndm_core::profile::ProbeProfile;
+use ndm_core::session::shutdown_signal;
+use ndm_core::{Event, EventBus, Session};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+use tracing::{info, warn};
+
+const INVENTORY_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Where the API listens
+pub enum Endpoint {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+#[derive(Clone)]
+struct ApiState {
+    session: Arc<Session>,
+    events: EventLog,
+    profiles: Vec<ProbeProfile>,
+    profile: ProbeProfile,
+    started_at: DateTime<Utc>,
+}
+
+pub async fn run(
+    config: &AppConfig,
+    args: &ScanArgs,
+    endpoint: Endpoint,
+    interval: Option<Duration>,
+    metrics: bool,
+) -> Result<()> {
+    // Anyone who can reach the API can cut devices off, so it stays on this host
+    if let Endpoint::Tcp(address) = &endpoint {
+        if !address.ip().is_loopback() {
+            return Err(anyhow::anyhow!(
+                "The API has no authentication and only listens on loopback, not {}",
+                address
+            ));
+        }
+    }
+
+    let interface = pick_interface(args.iface.as_deref())?;
+    let inventory_path = config.interface_inventory_path(&interface.name);
+    let devices: Arc<DeviceTable> = Arc::new(DashMap::new());
+    for device in inventory::load_for_interface(config, &interface.name)? {
+        devices.insert(device.id.clone(), device);
+    }
+    let bus = EventBus::new();
+    let events = EventLog::start(bus.subscribe_with_backpressure());
+    let session = Arc::new(start_session(config, args, interface, devices, bus)?);
+    let state = ApiState {
+        session: session.clone(),
+        events,
+        profiles: config.profiles(),
+        profile: pick_profile(config, args.profile.as_deref())?,
+        started_at: Utc::now(),
+    };
+
+    let save_devices = session.devices().clone();
+    let save_path = inventory_path.clone();
+    tokio::spawn(async move {
+        let mut ticker = time::interval(INVENTORY_SAVE_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(e) = inventory::save(&save_path, &save_devices) {
+                warn!(error = %e, "Failed to save inventory");
+            }
+        }
+    });
+
+    if let Some(interval) = interval {
+        let session = session.clone();
+        let profile = state.profile.clone();
+        tokio::spawn(async move {
+            let mut ticker = time::interval(interval);
+            // The scanner runs its own sweep on start
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                session.scan(profile.clone());
+            }
+        });
+    }
+
+    let socket_path = match &endpoint {
+        Endpoint::Unix(path) => Some(path.clone()),
+        Endpoint::Tcp(_) => None,
+    };
+    let app = router(state, metrics);
+    let result = tokio::select! {
+        result = serve(endpoint, app) => result,
+        result = session.wait() => result,
+        _ = shutdown_signal() => Ok(()),
+    };
+    session.shutdown().await;
+    if let Some(path) = socket_path {
+        let _ = std::fs::remove_file(path);
+    }
+    inventory::save(&inventory_path, session.devices())?;
+    result
+}
+
+async fn serve(endpoint: Endpoint, app: Router) -> Result<()> {
+    match endpoint {
+        Endpoint::Tcp(address) => {
+            let listener = tokio::net::TcpListener::bind(address)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", address, e))?;
+            info!(address = %listener.local_addr()?, "Listening");
+            axum::serve(listener, app).await?;
+        }
+        Endpoint::Unix(path) => serve_unix(path, app).await?,
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_unix(path: PathBuf, app: Router) -> Result<()> {
+    use std::fs::{self, DirBuilder, Permissions};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    // A socket left behind by an earlier run would make bind fail
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    // Bound inside a directory only we can enter and moved into place once it is
+    // 0600, so nobody else can connect while the permissions are still open
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a file path", path.display()))?;
+    let staging = path.with_file_name(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join("socket");
+    let listener = tokio::net::UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, Permissions::from_mode(0o600))?;
+        fs::rename(&staged, &path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    let listener =
+        listener.map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", path.display(), e))?;
+    info!(path = %path.display(), "Listening");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_path: PathBuf, _app: Router) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Unix sockets are not supported on this platform"
+    ))
+}
+
+fn router(state: ApiState, metrics: bool) -> Router {
+    let router = Router::new()
+        .route("/api/status", get(status))
+        .route("/api/profiles", get(profiles))
+        .route("/api/devices", get(list_devices))
+        .route("/api/devices/{id}", get(get_device))
+        .route("/api/devices/{id}/block", post(block_device))
+        .route("/api/devices/{id}/restore", post(restore_device))
+        .route("/api/scan", post(scan))
+        .route("/api/export", get(export_devices))
+        .route("/api/events", get(events))
+        .route("/api/log-level", get(log_level).put(set_log_level));
+    let router = if metrics {
+        router.route("/metrics", get(render_metrics))
+    } else {
+        router
+    };
+    router.with_state(state)
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+// A device as the API reports it, with its id flattened to a string usable in URLs
+#[derive(Serialize)]
+struct DeviceView {
+    key: String,
+    blocked: bool,
+    #[serde(flatten)]
+    device: NetworkDevice,
+}
+
+impl From<NetworkDevice> for DeviceView {
+    fn from(device: NetworkDevice) -> Self {
+        Self {
+            key: device.id.to_string(),
+            blocked: device.is_killed,
+            device,
+        }
+    }
+}
+
+fn find_device(state: &ApiState, key: &str) -> Result<NetworkDevice, ApiError> {
+    state
+        .session
+        .devices()
+        .get(&DeviceId::parse(key))
+        .map(|device| device.clone())
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("No device '{}'", key)))
+}
+
+#[derive(Serialize)]
+struct StatusView {
+    interface: String,
+    addresses: Vec<String>,
+    started_at: DateTime<Utc>,
+    profile: String,
+    devices: usize,
+    active: usize,
+    inactive: usize,
+    blocked: usize,
+}
+
+async fn status(State(state): State<ApiState>) -> Json<StatusView> {
+    let interface = state.session.interface();
+    let devices = state.session.devices();
+    let count = |wanted: DeviceStatus| {
+        devices
+            .iter()
+            .filter(|d| !d.is_killed && d.status == wanted)
+            .count()
+    };
+    Json(StatusView {
+        interface: interface.name.clone(),
+        addresses: interface.ips.iter().map(|ip| ip.to_string()).collect(),
+        started_at: state.started_at,
+        profile: state.profile.name.clone(),
+        devices: devices.len(),
+        active: count(DeviceStatus::Active),
+        inactive: count(DeviceStatus::Inactive),
+        blocked: devices.iter().filter(|d| d.is_killed).count(),
+    })
+}
+
+async fn profiles(State(state): State<ApiState>) -> Json<Vec<ProbeProfile>> {
+    Json(state.profiles)
+}
+
+#[derive(Deserialize)]
+struct DeviceQuery {
+    // active, inactive, blocked or unknown
+    status: Option<String>,
+    // Same matching as the GUI's search box
+    q: Option<String>,
+}
+
+async fn list_devices(
+    State(state): State<ApiState>,
+    Query(query): Query<DeviceQuery>,
+) -> Result<Json<Vec<DeviceView>>, ApiError> {
+    let status = query.status.as_deref().map(str::to_lowercase);
+    if let Some(status) = &status {
+        if !["active", "inactive", "blocked", "unknown"].contains(&status.as_str()) {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown status '{}'", status),
+            ));
+        }
+    }
+    let mut devices: Vec<NetworkDevice> = state
+        .session
+        .devices()
+        .iter()
+        .filter(|d| query.q.as_deref().is_none_or(|q| d.matches(q)))
+        .filter(|d| {
+            status
+                .as_deref()
+                .is_none_or(|wanted| d.status_name() == wanted)
+        })
+        .map(|d| d.value().clone())
+        .collect();
+    devices.sort_by_key(|d| (d.ip_address.parse::<IpAddr>().ok(), d.id.clone()));
+    Ok(Json(devices.into_iter().map(DeviceView::from).collect()))
+}
+
+async fn get_device(
+    State(state): State<ApiState>,
+    Path(key): Path<String>,
+) -> Result<Json<DeviceView>, ApiError> {
+    Ok(Json(find_device(&state, &key)?.into()))
+}
+
+async fn block_device(
+    State(state): State<ApiState>,
+    Path(key): Path<String>,
+) -> Result<Json<DeviceView>, ApiError> {
+    let device = find_device(&state, &key)?;
+    state.session.block(std::slice::from_ref(&device.id));
+    Ok(Json(find_device(&state, &key)?.into()))
+}
+
+async fn restore_device(
+    State(state): State<ApiState>,
+    Path(key): Path<String>,
+) -> Result<Json<DeviceView>, ApiError> {
+    let device = find_device(&state, &key)?;
+    state.session.restore(std::slice::from_ref(&device.id));
+    Ok(Json(find_device(&state, &key)?.into()))
+}
+
+#[derive(Deserialize)]
+struct ScanQuery {
+    profile: Option<String>,
+}
+
+// Scans run in the background, their results arrive through the device list
+async fn scan(
+    State(state): State<ApiState>,
+    Query(query): Query<ScanQuery>,
+) -> Result<(StatusCode, Json<ProbeProfile>), ApiError> {
+    let profile = match query.profile {
+        None => state.profile.clone(),
+        Some(name) => state
+            .profiles
+            .iter()
+            .find(|p| p.name == name)
+            .cloned()
+            .ok_or_else(|| {
+                ApiError(
+                    StatusCode::BAD_REQUEST,
+                    format!("Unknown probe profile '{}'", name),
+                )
+            })?,
+    };
+    state.session.scan(profile.clone());
+    Ok((StatusCode::ACCEPTED, Json(profile)))
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    format: Option<String>,
+}
+
+async fn export_devices(
+    State(state): State<ApiState>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let format = match query.format.as_deref().unwrap_or("csv") {
+        "csv" => ExportFormat::Csv,
+        "json" => ExportFormat::Json,
+        "html" => ExportFormat::Html,
+        other => {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown export format '{}'", other),
+            ))
+        }
+    };
+    let mut body = Vec::new();
+    export::export(state.session.devices(), format, &mut body)
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let content_type = match format {
+        ExportFormat::Csv => "text/csv; charset=utf-8",
+        ExportFormat::Json => "application/json",
+        ExportFormat::Html => "text/html; charset=utf-8",
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+async fn render_metrics(State(state): State<ApiState>) -> Response {
+    let body = state.session.metrics().render(state.session.devices());
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        body,
+    )
+        .into_response()
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogLevel {
+    level: String,
+}
+
+async fn log_level() -> Result<Json<LogLevel>, ApiError> {
+    logging::level()
+        .map(|level| Json(LogLevel { level }))
+        .ok_or_else(|| {
+            ApiError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Logging is not set up".to_string(),
+            )
+        })
+}
+
+// Takes a level or filter directives, e.g. {"level": "info,ndm_core::scanner=trace"}
+async fn set_log_level(Json(body): Json<LogLevel>) -> Result<Json<LogLevel>, ApiError> {
+    logging::set_level(&body.level)
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    log_level().await
+}
+
+#[derive(Deserialize)]
+struct EventQuery {
+    // Comma separated event types, all of them when missing
+    types: Option<String>,
+    // Replay what came after this event id before going live
+    since: Option<String>,
+}
+
+// Server-sent events. Each one carries its EventId, so an EventSource resumes
+// where it left off by itself after a reconnect.
+async fn events(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<EventQuery>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, ApiError> {
+    let types: Option<HashSet<String>> = match query.types.as_deref() {
+        None => None,
+        Some(types) => {
+            let types: HashSet<String> = types
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect();
+            if let Some(unknown) = types.iter().find(|t| !Event::KINDS.contains(&t.as_str())) {
+                return Err(ApiError(
+                    StatusCode::BAD_REQUEST,
+                    format!("Unknown event type '{}'", unknown),
+                ));
+            }
+            Some(types)
+        }
+    };
+    // An id we can't read was handed out by another version of the daemon, which
+    // calls for a resync like an id from any other earlier run
+    let foreign = EventId { epoch: 0, seq: 0 };
+    let since = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(foreign),
+        ),
+        None => query
+            .since
+            .as_deref()
+            .map(|since| since.parse().unwrap_or(foreign)),
+    };
+
+    let resume = state.events.resume(since);
+    let wanted = move |event: &Sequenced| {
+        types
+            .as_ref()
+            .is_none_or(|types| types.contains(event.event.kind()))
+    };
+    // Whatever the client missed is gone, so it has to reload the device list
+    let resync = resume
+        .gap
+        .then(|| sse::Event::default().event("resync").data("{}"));
+    let backlog: Vec<sse::Event> = resume
+        .backlog
+        .iter()
+        .filter(|event| wanted(event))
+        .map(|event| to_sse(event))
+        .collect();
+    let live = stream::unfold((resume.live, wanted), |(mut live, wanted)| async move {
+        loop {
+            match live.recv().await {
+                Ok(event) if wanted(&event) => return Some((to_sse(&event), (live, wanted))),
+                Ok(_) => continue,
+                // Ending the stream of a client that fell behind makes it
+                // reconnect and catch up from the history
+                Err(_) => return None,
+            }
+        }
+    });
+    let stream = stream::iter(resync.into_iter().chain(backlog))
+        .chain(live)
+        .map(Ok);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn to_sse(event: &Sequenced) -> sse::Event {
+    sse::Event::default()
+        .id(event.id.to_string())
+        .event(event.event.kind())
+        .data(serde_json::to_string(event).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndm_core::packet_io::MemoryPacketIo;
+    use ndm_core::SessionConfig;
+    use pnet::datalink::{MacAddr, NetworkInterface};
+    use serde_json::Value;
+    use std::net::Ipv4Addr;
+    use std::sync::Once;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const HOST_MAC: &str = "00:11:22:33:44:55";
+
+    // A session on canned traffic that knows one device
+    fn api_state() -> ApiState {
+        let interface = NetworkInterface {
+            name: "test0".to_string(),
+            description: String::new(),
+            index: 1,
+            mac: Some(MacAddr::new(0x02, 0, 0, 0, 0, 0x01)),
+            ips: vec!["192.168.50.1/29".parse().unwrap()],
+            flags: 0,
+        };
+        let devices: Arc<DeviceTable> = Arc::new(DashMap::new());
+        let mut device = NetworkDevice::new(
+            DeviceId::Mac(HOST_MAC.to_string()),
+            HOST_MAC.to_string(),
+            String::new(),
+        );
+        device.record_address("192.168.50.3".parse().unwrap(), Utc::now());
+        devices.insert(device.id.clone(), device);
+
+        let bus = EventBus::new();
+        let events = EventLog::start(bus.subscribe_with_backpressure());
+        let session = Arc::new(Session::start(
+            interface,
+            Arc::new(MemoryPacketIo::new()),
+            devices,
+            bus,
+            SessionConfig::default(),
+        ));
+        ApiState {
+            session,
+            events,
+            profiles: ProbeProfile::builtin(),
+            profile: ProbeProfile::default(),
+            started_at: Utc::now(),
+        }
+    }
+
+    // The API on a loopback port
+    async fn serve_api(metrics: bool) -> (SocketAddr, Arc<Session>) {
+        let state = api_state();
+        let session = state.session.clone();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(state, metrics)).await });
+        (address, session)
+    }
+
+    // Sends one HTTP/1.1 request with a JSON body and returns the status code and
+    // the response body
+    async fn exchange(
+        mut stream: impl AsyncRead + AsyncWrite + Unpin,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> (u16, String) {
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    async fn send(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let stream = TcpStream::connect(address).await.unwrap();
+        exchange(stream, method, path, body).await
+    }
+
+    async fn request(address: SocketAddr, method: &str, path: &str) -> (u16, Value) {
+        let (status, body) = send(address, method, path, "").await;
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lists_and_looks_up_devices() {
+        let (address, session) = serve_api(false).await;
+
+        let (status, body) = request(address, "GET", "/api/devices").await;
+        assert_eq!(status, 200);
+        let devices = body.as_array().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0]["key"], HOST_MAC);
+        assert_eq!(devices[0]["ip_address"], "192.168.50.3");
+
+        let (status, body) = request(address, "GET", "/api/devices?status=active").await;
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+
+        let (status, body) = request(address, "GET", &format!("/api/devices/{}", HOST_MAC)).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["mac_address"], HOST_MAC);
+
+        let (status, body) = request(address, "GET", "/api/devices/00:11:22:33:44:66").await;
+        assert_eq!(status, 404);
+        assert!(body["error"].is_string());
+
+        session.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_bad_status_and_format() {
+        let (address, session) = serve_api(false).await;
+
+        let (status, body) = request(address, "GET", "/api/devices?status=sleeping").await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "Unknown status 'sleeping'");
+
+        let (status, body) = request(address, "GET", "/api/export?format=xlsx").await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "Unknown export format 'xlsx'");
+
+        session.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocks_and_restores_devices() {
+        let (address, session) = serve_api(false).await;
+        let id = DeviceId::Mac(HOST_MAC.to_string());
+
+        let (status, body) =
+            request(address, "POST", &format!("/api/devices/{}/block", HOST_MAC)).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["blocked"], true);
+        assert!(session.devices().get(&id).unwrap().is_killed);
+
+        let (status, body) = request(address, "GET", "/api/devices?status=blocked").await;
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+
+        let (status, body) = request(
+            address,
+            "POST",
+            &format!("/api/devices/{}/restore", HOST_MAC),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["blocked"], false);
+        assert!(!session.devices().get(&id).unwrap().is_killed);
+
+        let (status, _) = request(address, "POST", "/api/devices/00:11:22:33:44:66/block").await;
+        assert_eq!(status, 404);
+
+        session.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn starts_scans_with_a_named_profile() {
+        let (address, session) = serve_api(false).await;
+        let mut events = session.subscribe();
+
+        let (status, body) = request(address, "POST", "/api/scan?profile=ARP%20only").await;
+        assert_eq!(status, 202);
+        assert_eq!(body["name"], "ARP only");
+        // It runs once the initial sweep has settled
+        let started = time::timeout(Duration::from_secs(15), async {
+            loop {
+                if let Some(Event::ScanStarted { profile }) = events.recv().await {
+                    if profile == "ARP only" {
+                        break;
+                    }
+                }
+            }
+        })
+        .await;
+        assert!(started.is_ok());
+
+        let (status, body) = request(address, "POST", "/api/scan?profile=everything").await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "Unknown probe profile 'everything'");
+
+        session.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_metrics_only_when_enabled() {
+        let (address, session) = serve_api(true).await;
+        let (status, body) = send(address, "GET", "/metrics", "").await;
+        assert_eq!(status, 200);
+        assert!(body.contains("ndm_devices{status=\"active\"} 1"));
+        assert!(body.contains("# TYPE ndm_frames_sent_total counter"));
+        session.shutdown().await;
+
+        let (address, session) = serve_api(false).await;
+        let (status, _) = send(address, "GET", "/metrics", "").await;
+        assert_eq!(status, 404);
+        session.shutdown().await;
+    }
+
+    // Logging is process-wide, so only this test sets it up
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reads_and_changes_the_log_level() {
+        static LOGGING: Once = Once::new();
+        let dir = std::env::temp_dir().join(format!("ndm-daemon-logs-{}", std::process::id()));
+        // Nothing reads the log file, so its guard can go straight away
+        LOGGING.call_once(|| drop(logging::init(&dir, "test", "off").unwrap()));
+        let (address, session) = serve_api(false).await;
+
+        let (status, body) = request(address, "GET", "/api/log-level").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["level"], "off");
+
+        let (status, body) = send(
+            address,
+            "PUT",
+            "/api/log-level",
+            r#"{"level": "off,ndm_cli=debug"}"#,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert!(body.contains("ndm_cli=debug"));
+
+        let (status, body) = send(
+            address,
+            "PUT",
+            "/api/log-level",
+            r#"{"level": "ndm_core=loud"}"#,
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(body.contains("Invalid log level"));
+
+        // Keep the other tests quiet
+        logging::set_level("off").unwrap();
+        session.shutdown().await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unix_socket_is_only_open_to_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("ndm-daemon-socket-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ndm.sock");
+        let state = api_state();
+        let session = state.session.clone();
+        tokio::spawn(serve_unix(path.clone(), router(state, false)));
+        time::timeout(Duration::from_secs(5), async {
+            while !path.exists() {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Only the socket is left, not the directory it was bound in
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (status, _) = exchange(stream, "GET", "/api/status", "").await;
+        assert_eq!(status, 200);
+
+        session.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
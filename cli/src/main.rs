@@ -0,0 +1,559 @@
+mod daemon;
+mod stream;
+
+use anyhow::Result;
+use chrono::{Local, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use dashmap::DashMap;
+use ndm_core::config::AppConfig;
+use ndm_core::events::Action;
+use ndm_core::export::{self, ExportFormat};
+use ndm_core::inventory;
+use ndm_core::logging;
+use ndm_core::models::{DeviceStatus, DeviceTable, NetworkDevice};
+use ndm_core::packet_io::LivePacketIo;
+use ndm_core::profile::ProbeProfile;
+use ndm_core::session::shutdown_signal;
+use ndm_core::{Event, EventBus, Session, SessionConfig};
+use pnet::datalink::{self, NetworkInterface};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+use tracing::warn;
+
+#[derive(Parser)]
+#[command(
+    name = "ndm",
+    version,
+    about = "Discover and list devices on the local network"
+)]
+struct Cli {
+    /// How results are printed
+    #[arg(long, short, value_enum, default_value_t = OutputFormat::Table, global = true)]
+    format: OutputFormat,
+    /// Log level or filter directives, e.g. debug or info,ndm_core::scanner=trace
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Jsonl,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List network interfaces that can be scanned
+    Interfaces,
+    /// Probe the network once and print the devices that answered
+    Scan {
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// How long to listen for replies, e.g. 10s, 1500ms, 2m
+        #[arg(long, default_value = "10s", value_parser = parse_duration)]
+        timeout: Duration,
+        /// Merge the results into the saved inventory
+        #[arg(long)]
+        save: bool,
+    },
+    /// Keep scanning and print devices and changes as they happen
+    Watch {
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Time between scans
+        #[arg(long, default_value = "60s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+    /// Keep scanning and serve devices, scans and blocking over a local JSON API
+    Daemon {
+        #[command(flatten)]
+        scan: ScanArgs,
+        /// Loopback address to listen on
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: SocketAddr,
+        /// Listen on this Unix socket instead
+        #[arg(long, conflicts_with = "listen")]
+        socket: Option<PathBuf>,
+        /// Time between scans, only on request when omitted
+        #[arg(long, value_parser = parse_duration)]
+        interval: Option<Duration>,
+        /// Also serve Prometheus metrics on /metrics
+        #[arg(long)]
+        metrics: bool,
+    },
+    /// Write the saved inventory as a CSV, JSON or HTML report
+    Export {
+        /// Report type, taken from the output file extension when omitted
+        #[arg(long = "type", value_enum)]
+        report: Option<ReportType>,
+        /// Output file, stdout when omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Interface whose inventory to read, the one with the default route when omitted
+        #[arg(long, short)]
+        iface: Option<String>,
+        /// Inventory file to read instead
+        #[arg(long, conflicts_with = "iface")]
+        inventory: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct ScanArgs {
+    /// Interface to scan, the one with the default route when omitted
+    #[arg(long, short)]
+    iface: Option<String>,
+    /// Probe profile name from the config file
+    #[arg(long, short)]
+    profile: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportType {
+    Csv,
+    Json,
+    Html,
+}
+
+impl From<ReportType> for ExportFormat {
+    fn from(report: ReportType) -> Self {
+        match report {
+            ReportType::Csv => ExportFormat::Csv,
+            ReportType::Json => ExportFormat::Json,
+            ReportType::Html => ExportFormat::Html,
+        }
+    }
+}
+
+// Accepts "10s", "1500ms", "2m" or a bare number of seconds
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", text))?;
+    let seconds = match unit {
+        "" | "s" => value,
+        "ms" => value / 1000.0,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(format!("unknown unit '{}' in '{}'", unit, text)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration '{}' is too long", text))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let loaded = AppConfig::load();
+    let config = loaded.as_ref().cloned().unwrap_or_default();
+    let level = cli.log_level.as_deref().unwrap_or(config.log_level());
+    let log_guard = match logging::init(&config.log_dir(), "ndm", level) {
+        Ok(guard) => Some(guard),
+        Err(e) => {
+            eprintln!("Logging to file is disabled: {:#}", e);
+            None
+        }
+    };
+    if let Err(e) = &loaded {
+        warn!(error = %e, "Failed to load config, using defaults");
+    }
+
+    let result = match cli.command {
+        Command::Interfaces => list_interfaces(cli.format),
+        Command::Scan {
+            scan,
+            timeout,
+            save,
+        } => run_scan(&config, &scan, timeout, save, cli.format).await,
+        Command::Watch { scan, interval } => run_watch(&config, &scan, interval, cli.format).await,
+        Command::Daemon {
+            scan,
+            listen,
+            socket,
+            interval,
+            metrics,
+        } => {
+            let endpoint = match socket {
+                Some(path) => daemon::Endpoint::Unix(path),
+                None => daemon::Endpoint::Tcp(listen),
+            };
+            daemon::run(&config, &scan, endpoint, interval, metrics).await
+        }
+        Command::Export {
+            report,
+            output,
+            iface,
+            inventory,
+        } => run_export(&config, report, output, iface, inventory),
+    };
+
+    drop(log_guard);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct InterfaceInfo {
+    name: String,
+    description: String,
+    mac_address: Option<String>,
+    ips: Vec<String>,
+    is_up: bool,
+    is_default: bool,
+}
+
+fn list_interfaces(format: OutputFormat) -> Result<()> {
+    let default_name = default_net::get_default_interface().ok().map(|i| i.name);
+    let interfaces: Vec<InterfaceInfo> = datalink::interfaces()
+        .into_iter()
+        .map(|i| InterfaceInfo {
+            is_default: default_name.as_deref() == Some(i.name.as_str()),
+            is_up: i.is_up(),
+            mac_address: i.mac.map(|m| m.to_string()),
+            ips: i.ips.iter().map(|ip| ip.to_string()).collect(),
+            description: i.description,
+            name: i.name,
+        })
+        .collect();
+
+    match format {
+        OutputFormat::Table => {
+            let rows = interfaces
+                .iter()
+                .map(|i| {
+                    vec![
+                        if i.is_default {
+                            format!("{} *", i.name)
+                        } else {
+                            i.name.clone()
+                        },
+                        i.mac_address.clone().unwrap_or_default(),
+                        if i.is_up { "up" } else { "down" }.to_string(),
+                        i.ips.join(", "),
+                    ]
+                })
+                .collect();
+            print_table(&["NAME", "MAC", "STATE", "ADDRESSES"], rows)
+        }
+        _ => print_items(format, &interfaces),
+    }
+}
+
+// The named interface, or the one holding the default route
+fn pick_interface(name: Option<&str>) -> Result<NetworkInterface> {
+    let interfaces = datalink::interfaces();
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            default_net::get_default_interface()
+                .map_err(|e| anyhow::anyhow!("No default interface, pass --iface: {}", e))?
+                .name
+        }
+    };
+    interfaces
+        .into_iter()
+        .find(|i| i.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Interface '{}' not found, see `ndm interfaces`", name))
+}
+
+fn pick_profile(config: &AppConfig, name: Option<&str>) -> Result<ProbeProfile> {
+    match name {
+        None => Ok(config.default_profile()),
+        Some(name) => config
+            .profiles()
+            .into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown probe profile '{}'", name)),
+    }
+}
+
+fn start_session(
+    config: &AppConfig,
+    args: &ScanArgs,
+    interface: NetworkInterface,
+    devices: Arc<DeviceTable>,
+    events: EventBus,
+) -> Result<Session> {
+    let session_config = SessionConfig {
+        profile: pick_profile(config, args.profile.as_deref())?,
+        ..SessionConfig::from_app_config(config)?
+    };
+    Ok(Session::start(
+        interface,
+        Arc::new(LivePacketIo),
+        devices,
+        events,
+        session_config,
+    ))
+}
+
+async fn run_scan(
+    config: &AppConfig,
+    args: &ScanArgs,
+    timeout: Duration,
+    save: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let interface = pick_interface(args.iface.as_deref())?;
+    let inventory_path = config.interface_inventory_path(&interface.name);
+    let devices: Arc<DeviceTable> = Arc::new(DashMap::new());
+    if save {
+        for device in inventory::load_for_interface(config, &interface.name)? {
+            devices.insert(device.id.clone(), device);
+        }
+    }
+
+    let started = Utc::now();
+    let events = EventBus::new();
+    let mut subscription = events.subscribe();
+    let session = start_session(config, args, interface, devices.clone(), events)?;
+    let deadline = time::sleep(timeout);
+    tokio::pin!(deadline);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    // Print warnings as they come in
+    let result = loop {
+        tokio::select! {
+            // The scanner only returns early when it failed to start
+            result = session.wait() => break result,
+            _ = &mut deadline => break Ok(()),
+            _ = &mut shutdown => break Ok(()),
+            Some(event) = subscription.recv() => {
+                if let Event::WarningRaised { message } = event {
+                    eprintln!("Warning: {}", message);
+                }
+            }
+        }
+    };
+    drop(subscription);
+    session.shutdown().await;
+
+    // Whatever was found before a failure is still worth keeping
+    if save {
+        inventory::save(&inventory_path, &devices)?;
+    }
+    result?;
+
+    let mut seen: Vec<NetworkDevice> = devices
+        .iter()
+        .filter(|d| d.last_seen >= started)
+        .map(|d| d.value().clone())
+        .collect();
+    seen.sort_by_key(|d| (d.ip_address.parse::<IpAddr>().ok(), d.id.clone()));
+    print_devices(format, &seen)
+}
+
+async fn run_watch(
+    config: &AppConfig,
+    args: &ScanArgs,
+    interval: Duration,
+    format: OutputFormat,
+) -> Result<()> {
+    let devices: Arc<DeviceTable> = Arc::new(DashMap::new());
+    let events = EventBus::new();
+    // Every event gets printed, even if that means holding the scanner up
+    let mut subscription = events.subscribe_with_backpressure();
+    let interface = pick_interface(args.iface.as_deref())?;
+    let session = start_session(config, args, interface, devices, events)?;
+    let profile = pick_profile(config, args.profile.as_deref())?;
+    let mut ticker = time::interval(interval);
+    // The scanner runs its own sweep on start, the first tick would repeat it
+    ticker.tick().await;
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    // Errors end the loop rather than returning, so the session still shuts down
+    let result = loop {
+        tokio::select! {
+            result = session.wait() => break result,
+            _ = &mut shutdown => break Ok(()),
+            _ = ticker.tick() => session.scan(profile.clone()),
+            Some(event) = subscription.recv() => match format {
+                OutputFormat::Table => print_event_line(&event),
+                _ => {
+                    if let Err(e) = print_event(format, &event) {
+                        break Err(e);
+                    }
+                }
+            },
+        }
+    };
+    drop(subscription);
+    session.shutdown().await;
+    result
+}
+
+fn print_event_line(event: &Event) {
+    let time = Local::now().format("%H:%M:%S");
+    match event {
+        Event::DeviceDiscovered { device } => println!(
+            "{}  {:<16} {:<18} {}",
+            time,
+            device.ip_address,
+            device.mac_address,
+            device.display_name()
+        ),
+        Event::DeviceUpdated { device } => println!(
+            "{}  {:<16} {:<18} {} (updated)",
+            time,
+            device.ip_address,
+            device.mac_address,
+            device.display_name()
+        ),
+        Event::DeviceWentInactive { ip_address, .. } => {
+            println!("{}  {} went inactive", time, ip_address)
+        }
+        Event::WarningRaised { message } => eprintln!("Warning: {}", message),
+        Event::ScanStarted { profile } => println!("{}  Scan started ({})", time, profile),
+        Event::ScanFinished { profile, diff } => {
+            println!("{}  Scan finished ({})", time, profile);
+            for change in diff.iter().flat_map(|diff| &diff.changes) {
+                println!("{}  {}", time, change);
+            }
+        }
+        Event::ActionApplied {
+            action, ip_address, ..
+        } => match action {
+            Action::Block => println!("{}  Blocked {}", time, ip_address),
+            Action::Restore => println!("{}  Restored {}", time, ip_address),
+        },
+    }
+}
+
+fn run_export(
+    config: &AppConfig,
+    report: Option<ReportType>,
+    output: Option<PathBuf>,
+    iface: Option<String>,
+    inventory_path: Option<PathBuf>,
+) -> Result<()> {
+    let saved = match inventory_path {
+        Some(path) => inventory::load(&path)?,
+        None => {
+            let interface = pick_interface(iface.as_deref())?;
+            inventory::load_for_interface(config, &interface.name)?
+        }
+    };
+    let devices = DashMap::new();
+    for device in saved {
+        devices.insert(device.id.clone(), device);
+    }
+
+    let format = report
+        .map(ExportFormat::from)
+        .or_else(|| output.as_deref().and_then(ExportFormat::from_path))
+        .unwrap_or(ExportFormat::Csv);
+    match output {
+        Some(output) => export::export_to_file(&devices, format, &output),
+        None => export::export(&devices, format, io::stdout().lock()),
+    }
+}
+
+fn print_devices(format: OutputFormat, devices: &[NetworkDevice]) -> Result<()> {
+    match format {
+        OutputFormat::Table => {
+            let rows = devices
+                .iter()
+                .map(|d| {
+                    let status = if d.is_killed {
+                        "Blocked"
+                    } else {
+                        match d.status {
+                            DeviceStatus::Active => "Active",
+                            DeviceStatus::Inactive => "Inactive",
+                            DeviceStatus::Blocked => "Blocked",
+                            DeviceStatus::Unknown => "Unknown",
+                        }
+                    };
+                    vec![
+                        d.ip_address.clone(),
+                        d.mac_address.clone(),
+                        d.vendor.chars().take(30).collect(),
+                        d.display_name().to_string(),
+                        d.open_ports()
+                            .iter()
+                            .map(|p| p.to_string())
+                            .collect::<Vec<_>>()
+                            .join(","),
+                        status.to_string(),
+                    ]
+                })
+                .collect();
+            print_table(&["IP", "MAC", "VENDOR", "NAME", "PORTS", "STATUS"], rows)
+        }
+        _ => print_items(format, devices),
+    }
+}
+
+// JSON prints one array, JSON Lines one object per line
+fn print_items<T: Serialize>(format: OutputFormat, items: &[T]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    match format {
+        OutputFormat::Jsonl => {
+            for item in items {
+                serde_json::to_writer(&mut out, item)?;
+                writeln!(out)?;
+            }
+        }
+        _ => {
+            serde_json::to_writer_pretty(&mut out, items)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_event(format: OutputFormat, event: &Event) -> Result<()> {
+    let mut out = io::stdout().lock();
+    if format == OutputFormat::Jsonl {
+        serde_json::to_writer(&mut out, event)?;
+    } else {
+        serde_json::to_writer_pretty(&mut out, event)?;
+    }
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) -> Result<()> {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = io::stdout().lock();
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = *width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    writeln!(out, "{}", line(headers.to_vec()))?;
+    for row in &rows {
+        writeln!(out, "{}", line(row.iter().map(String::as_str).collect()))?;
+    }
+    Ok(())
+}
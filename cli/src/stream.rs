@@ -0,0 +1,217 @@
+use chrono::Utc;
+use ndm_core::{Event, Subscription};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+// Events kept for clients resuming after a reconnect
+const HISTORY_LEN: usize = 1000;
+// Live events a client can fall behind by before its stream is ended. Kept
+// below HISTORY_LEN so whatever it missed can still be replayed on resume.
+const LIVE_CAPACITY: usize = 256;
+
+// What clients resume from, written "<epoch>-<seq>". Sequence numbers start over
+// when the daemon restarts, so the epoch tells the runs apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct EventId {
+    pub epoch: u64,
+    pub seq: u64,
+}
+
+impl fmt::Display for EventId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.epoch, self.seq)
+    }
+}
+
+impl FromStr for EventId {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, ()> {
+        let (epoch, seq) = text.trim().split_once('-').ok_or(())?;
+        Ok(Self {
+            epoch: epoch.parse().map_err(|_| ())?,
+            seq: seq.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+// An event with the id clients resume from
+#[derive(Debug, Serialize)]
+pub struct Sequenced {
+    #[serde(flatten)]
+    pub id: EventId,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+// What a client gets when it connects
+pub struct Resume {
+    pub backlog: Vec<Arc<Sequenced>>,
+    // Events it asked for had already dropped out of the history, or came from an
+    // earlier run of the daemon
+    pub gap: bool,
+    pub live: broadcast::Receiver<Arc<Sequenced>>,
+}
+
+struct History {
+    next_seq: u64,
+    events: VecDeque<Arc<Sequenced>>,
+}
+
+// Numbers every event published on the bus and keeps the latest ones around
+#[derive(Clone)]
+pub struct EventLog {
+    // When this run started, in milliseconds
+    epoch: u64,
+    history: Arc<Mutex<History>>,
+    live: broadcast::Sender<Arc<Sequenced>>,
+}
+
+impl EventLog {
+    pub fn start(mut subscription: Subscription) -> Self {
+        let (live, _) = broadcast::channel(LIVE_CAPACITY);
+        let log = Self {
+            epoch: Utc::now().timestamp_millis() as u64,
+            history: Arc::new(Mutex::new(History {
+                next_seq: 1,
+                events: VecDeque::new(),
+            })),
+            live,
+        };
+        let writer = log.clone();
+        tokio::spawn(async move {
+            while let Some(event) = subscription.recv().await {
+                writer.push(event);
+            }
+        });
+        log
+    }
+
+    fn push(&self, event: Event) {
+        let mut history = self.history.lock().unwrap();
+        let event = Arc::new(Sequenced {
+            id: EventId {
+                epoch: self.epoch,
+                seq: history.next_seq,
+            },
+            event,
+        });
+        history.next_seq += 1;
+        if history.events.len() == HISTORY_LEN {
+            history.events.pop_front();
+        }
+        history.events.push_back(event.clone());
+        // Sent under the lock so a client resuming concurrently sees each event
+        // exactly once, either in its backlog or live
+        let _ = self.live.send(event);
+    }
+
+    // Everything after `since`, or only what happens from now on without it
+    pub fn resume(&self, since: Option<EventId>) -> Resume {
+        let history = self.history.lock().unwrap();
+        let live = self.live.subscribe();
+        let Some(since) = since else {
+            return Resume {
+                backlog: Vec::new(),
+                gap: false,
+                live,
+            };
+        };
+        // Nothing from another run carries on from where the client was
+        if since.epoch != self.epoch {
+            return Resume {
+                backlog: Vec::new(),
+                gap: true,
+                live,
+            };
+        }
+        let oldest = history
+            .events
+            .front()
+            .map_or(history.next_seq, |event| event.id.seq);
+        Resume {
+            backlog: history
+                .events
+                .iter()
+                .filter(|event| event.id.seq > since.seq)
+                .cloned()
+                .collect(),
+            gap: since.seq + 1 < oldest || since.seq >= history.next_seq,
+            live,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndm_core::EventBus;
+
+    fn log_with(count: usize) -> EventLog {
+        let log = EventLog::start(EventBus::new().subscribe());
+        for n in 0..count {
+            log.push(Event::ScanStarted {
+                profile: n.to_string(),
+            });
+        }
+        log
+    }
+
+    fn id(log: &EventLog, seq: u64) -> Option<EventId> {
+        Some(EventId {
+            epoch: log.epoch,
+            seq,
+        })
+    }
+
+    #[test]
+    fn ids_round_trip() {
+        let id = EventId {
+            epoch: 1718000000000,
+            seq: 42,
+        };
+        assert_eq!(id.to_string(), "1718000000000-42");
+        assert_eq!("1718000000000-42".parse(), Ok(id));
+        assert!("42".parse::<EventId>().is_err());
+        assert!("x-42".parse::<EventId>().is_err());
+    }
+
+    #[tokio::test]
+    async fn resumes_after_last_seen_event() {
+        let log = log_with(10);
+        let resume = log.resume(id(&log, 7));
+        assert!(!resume.gap);
+        let seqs: Vec<u64> = resume.backlog.iter().map(|event| event.id.seq).collect();
+        assert_eq!(seqs, vec![8, 9, 10]);
+
+        let resume = log.resume(id(&log, 10));
+        assert!(!resume.gap);
+        assert!(resume.backlog.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_gap_for_events_no_longer_kept() {
+        let log = log_with(HISTORY_LEN + 5);
+        let resume = log.resume(id(&log, 2));
+        assert!(resume.gap);
+        assert_eq!(resume.backlog.len(), HISTORY_LEN);
+    }
+
+    // Clients that saw events 5 and 500 before the daemon restarted and published 10
+    #[tokio::test]
+    async fn reports_gap_for_ids_from_an_earlier_run() {
+        let log = log_with(10);
+        for seq in [5, 500] {
+            let resume = log.resume(Some(EventId {
+                epoch: log.epoch - 1,
+                seq,
+            }));
+            assert!(resume.gap);
+            assert!(resume.backlog.is_empty());
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use crate::models::{DeviceId, DeviceTable};
+
+// Marks devices for the killer to cut off. Returns how many were found.
+pub fn block(devices: &DeviceTable, ids: &[DeviceId]) -> usize {
+    set_killed(devices, ids, true)
+}
+
+// Lets blocked devices back on. Returns how many were found.
+pub fn restore(devices: &DeviceTable, ids: &[DeviceId]) -> usize {
+    set_killed(devices, ids, false)
+}
+
+pub fn block_all(devices: &DeviceTable) {
+    for mut device in devices.iter_mut() {
+        device.is_killed = true;
+    }
+}
+
+pub fn restore_all(devices: &DeviceTable) {
+    for mut device in devices.iter_mut() {
+        device.is_killed = false;
+    }
+}
+
+fn set_killed(devices: &DeviceTable, ids: &[DeviceId], killed: bool) -> usize {
+    let mut found = 0;
+    for id in ids {
+        if let Some(mut device) = devices.get_mut(id) {
+            device.is_killed = killed;
+            found += 1;
+        }
+    }
+    found
+}
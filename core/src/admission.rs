@@ -0,0 +1,44 @@
+use anyhow::Result;
+use ipnetwork::IpNetwork;
+use pnet::datalink::NetworkInterface;
+use std::net::IpAddr;
+
+// Decides which source addresses are allowed into the device table. Anything
+// outside these ranges is routed traffic and belongs to the gateway.
+#[derive(Debug, Clone, Default)]
+pub struct AdmissionPolicy {
+    ranges: Vec<IpNetwork>,
+}
+
+impl AdmissionPolicy {
+    // An empty range list admits every address, which is what offline replay
+    // wants when there is no interface to derive subnets from
+    pub fn new(ranges: Vec<IpNetwork>) -> Self {
+        Self { ranges }
+    }
+
+    // The interface's own subnets plus any explicitly configured ranges
+    pub fn for_interface(interface: &NetworkInterface, extra_ranges: &[IpNetwork]) -> Self {
+        let mut ranges: Vec<IpNetwork> = interface.ips.clone();
+        ranges.extend_from_slice(extra_ranges);
+        Self { ranges }
+    }
+
+    pub fn admits(&self, ip: IpAddr) -> bool {
+        if ip.is_unspecified() || ip.is_multicast() {
+            return false;
+        }
+        self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+// Parses a comma or whitespace separated list such as "10.0.0.0/8, 172.16.5.0/24"
+pub fn parse_ranges(text: &str) -> Result<Vec<IpNetwork>> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.parse::<IpNetwork>()
+                .map_err(|e| anyhow::anyhow!("Invalid range '{}': {}", part, e))
+        })
+        .collect()
+}
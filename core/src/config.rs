@@ -0,0 +1,120 @@
+use crate::logging;
+use crate::profile::ProbeProfile;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "network-device-manager";
+
+// Settings read from config.json in the data directory. Every field is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    // Extra profiles, a profile with the same name as a built-in one replaces it
+    pub profiles: Vec<ProbeProfile>,
+    pub default_profile: Option<String>,
+    // CIDR ranges admitted into the device table besides the interface's subnets
+    pub admission_ranges: Vec<String>,
+    // IEEE oui.csv to use on top of the bundled vendor database
+    pub oui_path: Option<PathBuf>,
+    // Resolver for reverse DNS lookups, e.g. "192.168.1.1:53"
+    pub dns_server: Option<SocketAddr>,
+    // Where device inventories are kept between runs, one per interface beside it
+    pub inventory_path: Option<PathBuf>,
+    // Level or filter directives like "info,ndm_core::scanner=debug"
+    pub log_level: Option<String>,
+    // Directory for the daily log files
+    pub log_dir: Option<PathBuf>,
+}
+
+impl AppConfig {
+    pub fn path() -> PathBuf {
+        data_dir().join("config.json")
+    }
+
+    // A missing file is not an error, it just means defaults
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))
+    }
+
+    pub fn oui_path(&self) -> PathBuf {
+        self.oui_path
+            .clone()
+            .unwrap_or_else(|| data_dir().join("oui.csv"))
+    }
+
+    pub fn inventory_path(&self) -> PathBuf {
+        self.inventory_path
+            .clone()
+            .unwrap_or_else(|| data_dir().join("inventory.json"))
+    }
+
+    // Each interface keeps its own inventory beside the configured one, e.g.
+    // inventory-eth0.json next to inventory.json, so networks don't get mixed up
+    pub fn interface_inventory_path(&self, interface: &str) -> PathBuf {
+        let shared = self.inventory_path();
+        let stem = shared
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("inventory");
+        // Windows names interfaces like \Device\NPF_{...}
+        let name: String = interface
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '_',
+            })
+            .collect();
+        shared.with_file_name(format!("{}-{}.json", stem, name))
+    }
+
+    pub fn log_dir(&self) -> PathBuf {
+        self.log_dir
+            .clone()
+            .unwrap_or_else(|| data_dir().join("logs"))
+    }
+
+    pub fn log_level(&self) -> &str {
+        self.log_level.as_deref().unwrap_or(logging::DEFAULT_LEVEL)
+    }
+
+    pub fn profiles(&self) -> Vec<ProbeProfile> {
+        let mut profiles = ProbeProfile::builtin();
+        for custom in &self.profiles {
+            match profiles.iter_mut().find(|p| p.name == custom.name) {
+                Some(existing) => *existing = custom.clone(),
+                None => profiles.push(custom.clone()),
+            }
+        }
+        profiles
+    }
+
+    pub fn default_profile(&self) -> ProbeProfile {
+        let profiles = self.profiles();
+        self.default_profile
+            .as_ref()
+            .and_then(|name| profiles.iter().find(|p| &p.name == name).cloned())
+            .unwrap_or_default()
+    }
+}
+
+// Per-user directory for the config file and anything else the app keeps on disk
+pub fn data_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.unwrap_or_else(|| PathBuf::from(".")).join(APP_DIR)
+}
@@ -0,0 +1,323 @@
+use crate::models::{DeviceId, DeviceTable, PortState};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// What a scan saw of one device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceSnapshot {
+    pub name: String,
+    pub ip_address: String,
+    pub mac_address: String,
+    pub open_ports: BTreeSet<u16>,
+}
+
+// The devices that answered a scan, keyed by identity
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Utc>,
+    pub devices: BTreeMap<DeviceId, DeviceSnapshot>,
+}
+
+impl Snapshot {
+    // Devices heard from since `since` count as present
+    pub fn capture(devices: &DeviceTable, since: DateTime<Utc>) -> Self {
+        let devices = devices
+            .iter()
+            .filter(|d| d.last_seen >= since)
+            .map(|d| {
+                let snapshot = DeviceSnapshot {
+                    name: d.display_name().to_string(),
+                    ip_address: d.ip_address.clone(),
+                    mac_address: d.mac_address.clone(),
+                    open_ports: d
+                        .ports
+                        .iter()
+                        .filter(|(_, state)| **state == PortState::Open)
+                        .map(|(port, _)| *port)
+                        .collect(),
+                };
+                (d.key().clone(), snapshot)
+            })
+            .collect();
+        Self {
+            taken_at: Utc::now(),
+            devices,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    NewDevice,
+    DeviceGone,
+    IpChanged { from: String, to: String },
+    MacChanged { from: String, to: String },
+    PortOpened { port: u16 },
+    PortClosed { port: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceChange {
+    pub id: DeviceId,
+    pub name: String,
+    pub ip_address: String,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+impl fmt::Display for DeviceChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let device = if self.name.is_empty() || self.name == self.ip_address {
+            self.ip_address.clone()
+        } else {
+            format!("{} ({})", self.name, self.ip_address)
+        };
+        match &self.change {
+            Change::NewDevice => write!(f, "New device {}", device),
+            Change::DeviceGone => write!(f, "{} is gone", device),
+            Change::IpChanged { from, to } => write!(f, "{} moved from {} to {}", device, from, to),
+            Change::MacChanged { from, to } => {
+                write!(f, "{} changed MAC from {} to {}", device, from, to)
+            }
+            Change::PortOpened { port } => write!(f, "{} opened port {}/tcp", device, port),
+            Change::PortClosed { port } => write!(f, "{} closed port {}/tcp", device, port),
+        }
+    }
+}
+
+// Everything that changed between two consecutive scans
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanDiff {
+    pub previous_at: DateTime<Utc>,
+    pub current_at: DateTime<Utc>,
+    pub changes: Vec<DeviceChange>,
+}
+
+impl ScanDiff {
+    pub fn between(previous: &Snapshot, current: &Snapshot) -> Self {
+        let mut changes = Vec::new();
+        let mut push = |id: &DeviceId, device: &DeviceSnapshot, change: Change| {
+            changes.push(DeviceChange {
+                id: id.clone(),
+                name: device.name.clone(),
+                ip_address: device.ip_address.clone(),
+                change,
+            });
+        };
+
+        // Other hardware answering at an address whose device went quiet is the
+        // address changing MAC, not one device arriving and another leaving
+        let mut replaced: BTreeMap<&DeviceId, &DeviceSnapshot> = BTreeMap::new();
+        let mut gone: Vec<(&DeviceId, &DeviceSnapshot)> = previous
+            .devices
+            .iter()
+            .filter(|(id, _)| !current.devices.contains_key(*id))
+            .collect();
+        for (id, now) in &current.devices {
+            if previous.devices.contains_key(id) || now.ip_address.is_empty() {
+                continue;
+            }
+            if let Some(index) = gone
+                .iter()
+                .position(|(_, b)| b.ip_address == now.ip_address)
+            {
+                replaced.insert(id, gone.remove(index).1);
+            }
+        }
+
+        for (id, now) in &current.devices {
+            let Some(before) = previous.devices.get(id) else {
+                match replaced.get(id) {
+                    Some(before) => push(
+                        id,
+                        now,
+                        Change::MacChanged {
+                            from: before.mac_address.clone(),
+                            to: now.mac_address.clone(),
+                        },
+                    ),
+                    None => push(id, now, Change::NewDevice),
+                }
+                continue;
+            };
+            if before.ip_address != now.ip_address {
+                push(
+                    id,
+                    now,
+                    Change::IpChanged {
+                        from: before.ip_address.clone(),
+                        to: now.ip_address.clone(),
+                    },
+                );
+            }
+            if before.mac_address != now.mac_address {
+                push(
+                    id,
+                    now,
+                    Change::MacChanged {
+                        from: before.mac_address.clone(),
+                        to: now.mac_address.clone(),
+                    },
+                );
+            }
+            for port in now.open_ports.difference(&before.open_ports) {
+                push(id, now, Change::PortOpened { port: *port });
+            }
+            for port in before.open_ports.difference(&now.open_ports) {
+                push(id, now, Change::PortClosed { port: *port });
+            }
+        }
+        for (id, before) in gone {
+            push(id, before, Change::DeviceGone);
+        }
+
+        Self {
+            previous_at: previous.taken_at,
+            current_at: current.taken_at,
+            changes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::NetworkDevice;
+    use chrono::Duration;
+    use dashmap::DashMap;
+
+    fn device(mac: &str, ip: &str, ports: &[u16]) -> (DeviceId, DeviceSnapshot) {
+        (
+            DeviceId::Mac(mac.to_string()),
+            DeviceSnapshot {
+                name: String::new(),
+                ip_address: ip.to_string(),
+                mac_address: mac.to_string(),
+                open_ports: ports.iter().copied().collect(),
+            },
+        )
+    }
+
+    fn snapshot(devices: Vec<(DeviceId, DeviceSnapshot)>) -> Snapshot {
+        Snapshot {
+            taken_at: Utc::now(),
+            devices: devices.into_iter().collect(),
+        }
+    }
+
+    fn changes(
+        previous: Vec<(DeviceId, DeviceSnapshot)>,
+        current: Vec<(DeviceId, DeviceSnapshot)>,
+    ) -> Vec<(String, Change)> {
+        ScanDiff::between(&snapshot(previous), &snapshot(current))
+            .changes
+            .into_iter()
+            .map(|c| (c.ip_address, c.change))
+            .collect()
+    }
+
+    #[test]
+    fn reports_new_and_gone_devices() {
+        let changes = changes(
+            vec![device("00:11:22:33:44:55", "192.168.1.20", &[])],
+            vec![device("00:11:22:33:44:66", "192.168.1.21", &[])],
+        );
+        assert_eq!(
+            changes,
+            vec![
+                ("192.168.1.21".to_string(), Change::NewDevice),
+                ("192.168.1.20".to_string(), Change::DeviceGone),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_address_and_port_changes() {
+        let changes = changes(
+            vec![device("00:11:22:33:44:55", "192.168.1.20", &[22, 80])],
+            vec![device("00:11:22:33:44:55", "192.168.1.30", &[80, 443])],
+        );
+        let ip = "192.168.1.30".to_string();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    ip.clone(),
+                    Change::IpChanged {
+                        from: "192.168.1.20".to_string(),
+                        to: "192.168.1.30".to_string(),
+                    }
+                ),
+                (ip.clone(), Change::PortOpened { port: 443 }),
+                (ip, Change::PortClosed { port: 22 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_hardware_at_a_gone_address_is_a_mac_change() {
+        let changes = changes(
+            vec![
+                device("00:11:22:33:44:55", "192.168.1.1", &[]),
+                device("00:11:22:33:44:77", "192.168.1.40", &[]),
+            ],
+            vec![device("00:11:22:33:44:66", "192.168.1.1", &[])],
+        );
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "192.168.1.1".to_string(),
+                    Change::MacChanged {
+                        from: "00:11:22:33:44:55".to_string(),
+                        to: "00:11:22:33:44:66".to_string(),
+                    }
+                ),
+                ("192.168.1.40".to_string(), Change::DeviceGone),
+            ]
+        );
+    }
+
+    #[test]
+    fn fingerprinted_device_changing_mac_is_a_mac_change() {
+        let id = DeviceId::Fingerprint("phone".to_string());
+        let (_, before) = device("02:00:00:00:00:01", "192.168.1.50", &[]);
+        let (_, now) = device("02:00:00:00:00:02", "192.168.1.50", &[]);
+        let changes = changes(vec![(id.clone(), before)], vec![(id, now)]);
+        assert_eq!(
+            changes,
+            vec![(
+                "192.168.1.50".to_string(),
+                Change::MacChanged {
+                    from: "02:00:00:00:00:01".to_string(),
+                    to: "02:00:00:00:00:02".to_string(),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn capture_keeps_devices_heard_from_since() {
+        let devices: DeviceTable = DashMap::new();
+        for (mac, age) in [("00:11:22:33:44:55", 0), ("00:11:22:33:44:66", 600)] {
+            let id = DeviceId::Mac(mac.to_string());
+            let mut device = NetworkDevice::new(id.clone(), mac.to_string(), String::new());
+            device.last_seen = Utc::now() - Duration::seconds(age);
+            device.ports.insert(80, PortState::Open);
+            device.ports.insert(22, PortState::Closed);
+            devices.insert(id, device);
+        }
+
+        let snapshot = Snapshot::capture(&devices, Utc::now() - Duration::seconds(60));
+        assert_eq!(snapshot.devices.len(), 1);
+        let device = &snapshot.devices[&DeviceId::Mac("00:11:22:33:44:55".to_string())];
+        assert_eq!(device.open_ports, BTreeSet::from([80]));
+    }
+}
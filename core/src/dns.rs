@@ -0,0 +1,264 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Minimal DNS wire format support, shared by mDNS, LLMNR and NetBIOS name service,
+// which all use the same message layout
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_NB: u16 = 32;
+pub const TYPE_NBSTAT: u16 = 33;
+pub const CLASS_IN: u16 = 1;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+
+#[derive(Debug, Clone)]
+pub struct Question {
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ptr(String),
+    Other(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    // Answer, authority and additional sections in that order
+    pub records: Vec<Record>,
+}
+
+impl Message {
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_RESPONSE != 0
+    }
+
+    pub fn opcode(&self) -> u16 {
+        (self.flags >> 11) & 0x0F
+    }
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 12 {
+            return None;
+        }
+        let id = read_u16(buf, 0)?;
+        let flags = read_u16(buf, 2)?;
+        let question_count = read_u16(buf, 4)?;
+        let record_count =
+            read_u16(buf, 6)? as usize + read_u16(buf, 8)? as usize + read_u16(buf, 10)? as usize;
+
+        let mut offset = 12;
+        let mut questions = Vec::new();
+        for _ in 0..question_count {
+            let (name, next) = read_name(buf, offset)?;
+            offset = next + 4;
+            questions.push(Question { name });
+        }
+
+        let mut records = Vec::new();
+        for _ in 0..record_count {
+            let (name, next) = read_name(buf, offset)?;
+            let rtype = read_u16(buf, next)?;
+            let ttl = read_u32(buf, next + 4)?;
+            let length = read_u16(buf, next + 8)? as usize;
+            let start = next + 10;
+            let rdata = buf.get(start..start + length)?;
+            let data = match (rtype, length) {
+                (TYPE_A, 4) => RecordData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+                (TYPE_AAAA, 16) => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(rdata);
+                    RecordData::Aaaa(Ipv6Addr::from(octets))
+                }
+                (TYPE_PTR, _) => RecordData::Ptr(read_name(buf, start)?.0),
+                _ => RecordData::Other(rdata.to_vec()),
+            };
+            offset = start + length;
+            records.push(Record {
+                name,
+                rtype,
+                ttl,
+                data,
+            });
+        }
+
+        Some(Self {
+            id,
+            flags,
+            questions,
+            records,
+        })
+    }
+}
+
+// Builds a single-question query. `flags` carries RD for unicast DNS, and the
+// class can have the mDNS unicast-response bit set.
+pub fn build_query(id: u16, flags: u16, name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12 + name.len() + 6);
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&flags.to_be_bytes());
+    buf.extend_from_slice(&1u16.to_be_bytes());
+    buf.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    write_name(&mut buf, name);
+    buf.extend_from_slice(&qtype.to_be_bytes());
+    buf.extend_from_slice(&qclass.to_be_bytes());
+    buf
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() {
+            continue;
+        }
+        let label = &label.as_bytes()[..label.len().min(63)];
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+fn read_name(buf: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bounds the number of compression pointers followed so a looping message can't hang us
+    let mut jumps = 0;
+    loop {
+        let length = *buf.get(offset)? as usize;
+        if length == 0 {
+            end.get_or_insert(offset + 1);
+            break;
+        }
+        if length & 0xC0 == 0xC0 {
+            let pointer = ((length & 0x3F) << 8) | *buf.get(offset + 1)? as usize;
+            end.get_or_insert(offset + 2);
+            jumps += 1;
+            if jumps > 16 {
+                return None;
+            }
+            offset = pointer;
+            continue;
+        }
+        let label = buf.get(offset + 1..offset + 1 + length)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + length;
+    }
+    Some((labels.join("."), end?))
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *buf.get(offset)?,
+        *buf.get(offset + 1)?,
+    ]))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes([
+        *buf.get(offset)?,
+        *buf.get(offset + 1)?,
+        *buf.get(offset + 2)?,
+        *buf.get(offset + 3)?,
+    ]))
+}
+
+// NetBIOS names travel "first-level encoded": 16 bytes (15 name + 1 suffix) with
+// each nibble mapped to 'A'..'P'. Returns the trimmed name and its suffix byte.
+pub fn decode_netbios_name(encoded: &str) -> Option<(String, u8)> {
+    let label = encoded.split('.').next()?.as_bytes();
+    if label.len() != 32 {
+        return None;
+    }
+    let mut raw = [0u8; 16];
+    for (i, pair) in label.chunks(2).enumerate() {
+        let high = pair[0].checked_sub(b'A')?;
+        let low = pair[1].checked_sub(b'A')?;
+        if high > 15 || low > 15 {
+            return None;
+        }
+        raw[i] = (high << 4) | low;
+    }
+    let name = String::from_utf8_lossy(&raw[..15]).trim_end().to_string();
+    Some((name, raw[15]))
+}
+
+pub fn encode_netbios_name(name: &str, suffix: u8) -> String {
+    let mut raw = [b' '; 16];
+    for (i, byte) in name.bytes().take(15).enumerate() {
+        raw[i] = byte.to_ascii_uppercase();
+    }
+    raw[15] = suffix;
+    // The wildcard name is padded with NULs rather than spaces
+    if name == "*" {
+        raw[1..].fill(0);
+    }
+    raw.iter()
+        .flat_map(|b| [b'A' + (b >> 4), b'A' + (b & 0x0F)])
+        .map(char::from)
+        .collect()
+}
+
+// 192.168.1.20 -> 20.1.168.192.in-addr.arpa
+pub fn reverse_name(ip: Ipv4Addr) -> String {
+    let o = ip.octets();
+    format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_compressed_names() {
+        let mut buf = build_query(7, 0, "printer.local", TYPE_A, CLASS_IN);
+        buf[2..4].copy_from_slice(&0x8400u16.to_be_bytes());
+        buf[6..8].copy_from_slice(&1u16.to_be_bytes());
+        // Answer named by a pointer back to the question
+        buf.extend_from_slice(&[0xC0, 0x0C]);
+        buf.extend_from_slice(&TYPE_A.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&120u32.to_be_bytes());
+        buf.extend_from_slice(&4u16.to_be_bytes());
+        buf.extend_from_slice(&[192, 168, 1, 20]);
+
+        let message = Message::parse(&buf).unwrap();
+        assert!(message.is_response());
+        assert_eq!(message.questions[0].name, "printer.local");
+        let record = &message.records[0];
+        assert_eq!(record.name, "printer.local");
+        assert_eq!(record.ttl, 120);
+        assert!(matches!(record.data, RecordData::A(ip) if ip == Ipv4Addr::new(192, 168, 1, 20)));
+    }
+
+    #[test]
+    fn self_referencing_pointer_is_rejected() {
+        let mut buf = vec![0, 1, 0x84, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        // The question name points at itself
+        buf.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1]);
+        assert!(Message::parse(&buf).is_none());
+    }
+
+    #[test]
+    fn netbios_names_round_trip() {
+        let encoded = encode_netbios_name("desktop-1", 0x20);
+        assert_eq!(encoded.len(), 32);
+        assert_eq!(
+            decode_netbios_name(&encoded),
+            Some(("DESKTOP-1".to_string(), 0x20))
+        );
+        assert_eq!(decode_netbios_name("not a netbios name"), None);
+    }
+}
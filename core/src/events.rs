@@ -0,0 +1,249 @@
+use crate::diff::ScanDiff;
+use crate::models::{DeviceId, NetworkDevice};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::{broadcast, mpsc};
+use tracing::debug;
+
+// Events a subscriber can fall behind by. Past that, most subscribers lose the
+// oldest ones, while those that asked for backpressure hold publishers up until
+// they catch up.
+pub const SUBSCRIBER_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Block,
+    Restore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    DeviceDiscovered {
+        device: Box<NetworkDevice>,
+    },
+    // A known device moved to another address, came back or got a name
+    DeviceUpdated {
+        device: Box<NetworkDevice>,
+    },
+    DeviceWentInactive {
+        id: DeviceId,
+        ip_address: String,
+    },
+    WarningRaised {
+        message: String,
+    },
+    ScanStarted {
+        profile: String,
+    },
+    // The sweep the scanner runs on start has nothing to compare against
+    ScanFinished {
+        profile: String,
+        diff: Option<ScanDiff>,
+    },
+    // The killer started or stopped cutting a device off
+    ActionApplied {
+        action: Action,
+        id: DeviceId,
+        ip_address: String,
+    },
+}
+
+impl Event {
+    // Every value `kind` can return
+    pub const KINDS: [&'static str; 7] = [
+        "device_discovered",
+        "device_updated",
+        "device_went_inactive",
+        "warning_raised",
+        "scan_started",
+        "scan_finished",
+        "action_applied",
+    ];
+
+    // The `type` tag the event serializes with
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::DeviceDiscovered { .. } => "device_discovered",
+            Event::DeviceUpdated { .. } => "device_updated",
+            Event::DeviceWentInactive { .. } => "device_went_inactive",
+            Event::WarningRaised { .. } => "warning_raised",
+            Event::ScanStarted { .. } => "scan_started",
+            Event::ScanFinished { .. } => "scan_finished",
+            Event::ActionApplied { .. } => "action_applied",
+        }
+    }
+}
+
+enum Queue {
+    Lossy(broadcast::Receiver<Event>),
+    Waiting(mpsc::Receiver<Event>),
+}
+
+// Events published since subscribing, in order
+pub struct Subscription {
+    queue: Queue,
+    missed: u64,
+}
+
+impl Subscription {
+    // None once the bus is gone
+    pub async fn recv(&mut self) -> Option<Event> {
+        match &mut self.queue {
+            Queue::Lossy(receiver) => loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some(event),
+                    Err(RecvError::Lagged(missed)) => lagged(&mut self.missed, missed),
+                    Err(RecvError::Closed) => return None,
+                }
+            },
+            Queue::Waiting(receiver) => receiver.recv().await,
+        }
+    }
+
+    // None when nothing is queued
+    pub fn try_recv(&mut self) -> Option<Event> {
+        match &mut self.queue {
+            Queue::Lossy(receiver) => loop {
+                match receiver.try_recv() {
+                    Ok(event) => return Some(event),
+                    Err(TryRecvError::Lagged(missed)) => lagged(&mut self.missed, missed),
+                    Err(_) => return None,
+                }
+            },
+            Queue::Waiting(receiver) => receiver.try_recv().ok(),
+        }
+    }
+
+    // How many events were dropped because this subscriber fell behind, since the
+    // last call
+    pub fn take_missed(&mut self) -> u64 {
+        std::mem::take(&mut self.missed)
+    }
+}
+
+fn lagged(total: &mut u64, missed: u64) {
+    debug!(missed, "Subscriber fell behind, dropped the oldest events");
+    *total += missed;
+}
+
+// Fans every event out to all subscribers. Cloning gives another handle on the
+// same bus.
+#[derive(Clone)]
+pub struct EventBus {
+    lossy: broadcast::Sender<Event>,
+    waiting: Arc<Mutex<Vec<mpsc::Sender<Event>>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            lossy: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+            waiting: Arc::default(),
+        }
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Receives everything published from now on, unless it falls more than
+    // SUBSCRIBER_CAPACITY events behind. Publishers never wait for it.
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            queue: Queue::Lossy(self.lossy.subscribe()),
+            missed: 0,
+        }
+    }
+
+    // Receives everything published from now on. Publishers wait while its queue
+    // is full, so it must keep reading.
+    pub fn subscribe_with_backpressure(&self) -> Subscription {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_CAPACITY);
+        self.waiting.lock().unwrap().push(sender);
+        Subscription {
+            queue: Queue::Waiting(receiver),
+            missed: 0,
+        }
+    }
+
+    // Waits while any backpressure subscriber's queue is full. Subscribers that
+    // were dropped are forgotten.
+    pub async fn publish(&self, event: Event) {
+        // Fails only when there are no lossy subscribers
+        let _ = self.lossy.send(event.clone());
+        let subscribers = self.waiting.lock().unwrap().clone();
+        let mut closed = false;
+        for subscriber in &subscribers {
+            if subscriber.send(event.clone()).await.is_err() {
+                closed = true;
+            }
+        }
+        if closed {
+            self.waiting
+                .lock()
+                .unwrap()
+                .retain(|subscriber| !subscriber.is_closed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time;
+
+    fn scan_started(n: usize) -> Event {
+        Event::ScanStarted {
+            profile: n.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_subscriber_never_holds_publishers_up() {
+        let bus = EventBus::new();
+        let mut idle = bus.subscribe();
+        let total = SUBSCRIBER_CAPACITY + 10;
+        time::timeout(Duration::from_secs(5), async {
+            for n in 0..total {
+                bus.publish(scan_started(n)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // The oldest events made room for the latest
+        let Some(Event::ScanStarted { profile }) = idle.try_recv() else {
+            panic!("expected an event");
+        };
+        assert_eq!(profile, "10");
+        assert_eq!(idle.take_missed(), 10);
+        assert_eq!(idle.take_missed(), 0);
+    }
+
+    #[tokio::test]
+    async fn backpressure_subscriber_gets_every_event() {
+        let bus = EventBus::new();
+        let mut reader = bus.subscribe_with_backpressure();
+        let total = SUBSCRIBER_CAPACITY * 2;
+        let publisher = bus.clone();
+        tokio::spawn(async move {
+            for n in 0..total {
+                publisher.publish(scan_started(n)).await;
+            }
+        });
+
+        for n in 0..total {
+            let Some(Event::ScanStarted { profile }) = reader.recv().await else {
+                panic!("expected an event");
+            };
+            assert_eq!(profile, n.to_string());
+        }
+        assert_eq!(reader.take_missed(), 0);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::models::{DeviceStatus, DeviceTable, NetworkDevice};
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Csv, ExportFormat::Json, ExportFormat::Html];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            "html" | "htm" => Some(ExportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+// One line of a report. Lists are joined so every format has the same columns.
+#[derive(Debug, Serialize)]
+struct ExportRow {
+    ip_address: String,
+    ipv6_addresses: String,
+    mac_address: String,
+    vendor: String,
+    hostname: String,
+    name: String,
+    tags: String,
+    notes: String,
+    status: String,
+    open_ports: String,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+}
+
+impl ExportRow {
+    fn new(device: &NetworkDevice) -> Self {
+        let status = if device.is_killed {
+            "Blocked".to_string()
+        } else {
+            match device.status {
+                DeviceStatus::Active => "Active",
+                DeviceStatus::Inactive => "Inactive",
+                DeviceStatus::Blocked => "Blocked",
+                DeviceStatus::Unknown => "Unknown",
+            }
+            .to_string()
+        };
+        Self {
+            ip_address: device.ip_address.clone(),
+            ipv6_addresses: device.ipv6_addresses.join(" "),
+            mac_address: device.mac_address.clone(),
+            vendor: device.vendor.clone(),
+            hostname: device.hostname.clone(),
+            name: device.labels.name.clone(),
+            tags: device.labels.tags.join(", "),
+            notes: device.labels.notes.clone(),
+            status,
+            open_ports: device
+                .open_ports()
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            first_seen: device.first_seen,
+            last_seen: device.last_seen,
+        }
+    }
+}
+
+// Snapshot of the table in the same order the UI shows it
+fn rows(devices: &DeviceTable) -> Vec<ExportRow> {
+    let mut devices: Vec<NetworkDevice> = devices.iter().map(|d| d.value().clone()).collect();
+    devices.sort_by_key(|d| (d.ip_address.parse::<IpAddr>().ok(), d.id.clone()));
+    devices.iter().map(ExportRow::new).collect()
+}
+
+pub fn export(devices: &DeviceTable, format: ExportFormat, writer: impl Write) -> Result<()> {
+    let rows = rows(devices);
+    match format {
+        ExportFormat::Csv => write_csv(&rows, writer),
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(writer, &rows)?;
+            Ok(())
+        }
+        ExportFormat::Html => write_html(&rows, writer),
+    }
+}
+
+pub fn export_to_file(devices: &DeviceTable, format: ExportFormat, path: &Path) -> Result<()> {
+    let file = File::create(path)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    export(devices, format, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_csv(rows: &[ExportRow], writer: impl Write) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    for row in rows {
+        csv.serialize(row)?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+const HTML_STYLE: &str =
+    "body{font-family:Segoe UI,Helvetica,Arial,sans-serif;margin:2em;color:#222}\
+h1{font-size:1.4em}p.meta{color:#666}\
+table{border-collapse:collapse;width:100%;font-size:.9em}\
+th,td{border:1px solid #ddd;padding:4px 8px;text-align:left;vertical-align:top}\
+th{background:#f5f5f5}tr:nth-child(even){background:#fafafa}\
+.Active{color:#329632}.Inactive{color:#646464}.Blocked{color:#c83232}";
+
+// A single file with inline styles so it can be mailed or opened offline
+fn write_html(rows: &[ExportRow], mut writer: impl Write) -> Result<()> {
+    let generated = Local::now().format("%Y-%m-%d %H:%M");
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>Network device report</title>")?;
+    writeln!(writer, "<style>{}</style></head><body>", HTML_STYLE)?;
+    writeln!(writer, "<h1>Network device report</h1>")?;
+    writeln!(
+        writer,
+        "<p class=\"meta\">Generated {} &middot; {} devices</p>",
+        generated,
+        rows.len()
+    )?;
+    writeln!(writer, "<table><thead><tr>")?;
+    for heading in [
+        "IP Address",
+        "MAC Address",
+        "Vendor",
+        "Hostname",
+        "Name",
+        "Tags",
+        "Status",
+        "Open Ports",
+        "First Seen",
+        "Last Seen",
+        "Notes",
+    ] {
+        write!(writer, "<th>{}</th>", heading)?;
+    }
+    writeln!(writer, "</tr></thead><tbody>")?;
+    for row in rows {
+        let mut address = escape(&row.ip_address);
+        if !row.ipv6_addresses.is_empty() {
+            address.push_str(&format!(
+                "<br><small>{}</small>",
+                escape(&row.ipv6_addresses)
+            ));
+        }
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td class=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            address,
+            escape(&row.mac_address),
+            escape(&row.vendor),
+            escape(&row.hostname),
+            escape(&row.name),
+            escape(&row.tags),
+            row.status,
+            row.status,
+            escape(&row.open_ports),
+            row.first_seen
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            row.last_seen.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            escape(&row.notes).replace('\n', "<br>"),
+        )?;
+    }
+    writeln!(writer, "</tbody></table></body></html>")?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DeviceId, PortState};
+    use dashmap::DashMap;
+
+    // A device whose hostname and labels came off the network or a customer's sheet
+    fn devices() -> DeviceTable {
+        let id = DeviceId::Mac("00:11:22:33:44:55".to_string());
+        let mut device = NetworkDevice::new(
+            id.clone(),
+            "00:11:22:33:44:55".to_string(),
+            "Acme, Inc.".to_string(),
+        );
+        device.record_address("192.168.1.20".parse().unwrap(), Utc::now());
+        device.hostname = "<script>alert('x')</script>".to_string();
+        device.labels.name = "Front \"desk\" & lobby".to_string();
+        device.labels.tags = vec!["office".to_string(), "printer".to_string()];
+        device.ports.insert(80, PortState::Open);
+        device.ports.insert(443, PortState::Open);
+        device.ports.insert(22, PortState::Closed);
+        let devices = DashMap::new();
+        devices.insert(id, device);
+        devices
+    }
+
+    fn exported(format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        export(&devices(), format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn html_escapes_network_supplied_text() {
+        let html = exported(ExportFormat::Html);
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;"));
+        assert!(html.contains("Front &quot;desk&quot; &amp; lobby"));
+        assert!(html.contains("<td class=\"Active\">Active</td>"));
+    }
+
+    #[test]
+    fn csv_has_a_header_and_one_row_per_device() {
+        let csv = exported(ExportFormat::Csv);
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let headers: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+        assert_eq!(
+            headers,
+            [
+                "ip_address",
+                "ipv6_addresses",
+                "mac_address",
+                "vendor",
+                "hostname",
+                "name",
+                "tags",
+                "notes",
+                "status",
+                "open_ports",
+                "first_seen",
+                "last_seen",
+            ]
+        );
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(&row[0], "192.168.1.20");
+        assert_eq!(&row[2], "00:11:22:33:44:55");
+        assert_eq!(&row[3], "Acme, Inc.");
+        assert_eq!(&row[6], "office, printer");
+        assert_eq!(&row[8], "Active");
+        assert_eq!(&row[9], "80 443");
+    }
+
+    #[test]
+    fn json_lists_every_device() {
+        let json: serde_json::Value = serde_json::from_str(&exported(ExportFormat::Json)).unwrap();
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["hostname"], "<script>alert('x')</script>");
+        assert_eq!(rows[0]["open_ports"], "80 443");
+    }
+}
@@ -0,0 +1,225 @@
+use crate::dns::{self, Message, RecordData};
+use pnet::packet::udp::UdpPacket;
+use pnet::packet::Packet;
+use std::net::IpAddr;
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+const NBNS_PORT: u16 = 137;
+const MDNS_PORT: u16 = 5353;
+const LLMNR_PORT: u16 = 5355;
+
+const DHCP_MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+const DHCP_OPTION_HOSTNAME: u8 = 12;
+const DHCP_OPTION_END: u8 = 255;
+const DHCP_OPTION_PAD: u8 = 0;
+
+const NBNS_OPCODE_REGISTRATION: u16 = 5;
+const NBNS_OPCODE_REFRESH: u16 = 8;
+const NB_FLAG_GROUP: u16 = 0x8000;
+
+// Pulls a name the sender announces for itself out of a broadcast or multicast
+// UDP datagram: mDNS and LLMNR answers, NetBIOS name registrations and the DHCP
+// hostname option. Names are only taken when they belong to `source`.
+pub fn passive_hostname(source: IpAddr, udp: &UdpPacket) -> Option<String> {
+    let payload = udp.payload();
+    match (udp.get_source(), udp.get_destination()) {
+        (MDNS_PORT, _) => from_name_answer(source, payload, ".local"),
+        (LLMNR_PORT, _) => from_name_answer(source, payload, ""),
+        (NBNS_PORT, NBNS_PORT) => from_netbios_registration(payload),
+        (DHCP_CLIENT_PORT, DHCP_SERVER_PORT) => from_dhcp_request(payload),
+        _ => None,
+    }
+}
+
+// mDNS announcements and LLMNR responses carry A/AAAA records mapping the
+// responder's name to its own address, and mDNS adds the matching reverse PTR
+fn from_name_answer(source: IpAddr, payload: &[u8], suffix: &str) -> Option<String> {
+    let message = Message::parse(payload)?;
+    if !message.is_response() {
+        return None;
+    }
+    let reverse = match source {
+        IpAddr::V4(ip) => Some(dns::reverse_name(ip)),
+        IpAddr::V6(_) => None,
+    };
+    message
+        .records
+        .iter()
+        .find_map(|record| match &record.data {
+            RecordData::A(ip) if IpAddr::V4(*ip) == source => clean_name(&record.name, suffix),
+            RecordData::Aaaa(ip) if IpAddr::V6(*ip) == source => clean_name(&record.name, suffix),
+            RecordData::Ptr(target) if reverse.as_deref() == Some(record.name.as_str()) => {
+                clean_name(target, suffix)
+            }
+            _ => None,
+        })
+}
+
+fn from_netbios_registration(payload: &[u8]) -> Option<String> {
+    let message = Message::parse(payload)?;
+    if message.is_response()
+        || !matches!(
+            message.opcode(),
+            NBNS_OPCODE_REGISTRATION | NBNS_OPCODE_REFRESH
+        )
+    {
+        return None;
+    }
+    let question = message.questions.first()?;
+    let (name, suffix) = dns::decode_netbios_name(&question.name)?;
+
+    // Only the workstation (0x00) and file server (0x20) names identify the machine,
+    // and group names such as the workgroup are shared by many hosts
+    if suffix != 0x00 && suffix != 0x20 {
+        return None;
+    }
+    let is_group = message.records.iter().any(|record| match &record.data {
+        RecordData::Other(data) if record.rtype == dns::TYPE_NB && data.len() >= 2 => {
+            u16::from_be_bytes([data[0], data[1]]) & NB_FLAG_GROUP != 0
+        }
+        _ => false,
+    });
+    if is_group || name.is_empty() {
+        return None;
+    }
+    Some(name)
+}
+
+fn from_dhcp_request(payload: &[u8]) -> Option<String> {
+    // op(1) htype(1) hlen(1) hops(1) xid(4) secs(2) flags(2) 4 addresses(16)
+    // chaddr(16) sname(64) file(128), then the magic cookie and options
+    const OPTIONS_OFFSET: usize = 240;
+    if payload.len() < OPTIONS_OFFSET || payload[0] != 1 || payload[236..240] != DHCP_MAGIC_COOKIE {
+        return None;
+    }
+
+    let mut offset = OPTIONS_OFFSET;
+    while offset < payload.len() {
+        let code = payload[offset];
+        match code {
+            DHCP_OPTION_PAD => {
+                offset += 1;
+                continue;
+            }
+            DHCP_OPTION_END => break,
+            _ => {}
+        }
+        let length = *payload.get(offset + 1)? as usize;
+        let value = payload.get(offset + 2..offset + 2 + length)?;
+        if code == DHCP_OPTION_HOSTNAME {
+            return clean_name(&String::from_utf8_lossy(value), "");
+        }
+        offset += 2 + length;
+    }
+    None
+}
+
+fn clean_name(name: &str, suffix: &str) -> Option<String> {
+    let name = name.trim().trim_end_matches('\0').trim_end_matches('.');
+    let name = name.strip_suffix(suffix).unwrap_or(name);
+    if name.is_empty() || name.chars().any(|c| c.is_control()) {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::udp::MutableUdpPacket;
+    use std::net::Ipv4Addr;
+
+    const HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    fn hostname(source_port: u16, destination_port: u16, payload: &[u8]) -> Option<String> {
+        let mut buffer = vec![0u8; 8 + payload.len()];
+        let mut udp = MutableUdpPacket::new(&mut buffer).unwrap();
+        udp.set_source(source_port);
+        udp.set_destination(destination_port);
+        udp.set_length((8 + payload.len()) as u16);
+        udp.set_payload(payload);
+        passive_hostname(HOST, &UdpPacket::new(&buffer).unwrap())
+    }
+
+    fn record(buf: &mut Vec<u8>, rtype: u16, rdata: &[u8]) {
+        buf.extend_from_slice(&rtype.to_be_bytes());
+        buf.extend_from_slice(&dns::CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&120u32.to_be_bytes());
+        buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(rdata);
+    }
+
+    // An unsolicited mDNS response with one A record for `address`
+    fn mdns_announcement(address: [u8; 4]) -> Vec<u8> {
+        let mut buf = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        buf.extend_from_slice(b"\x07printer\x05local\x00");
+        record(&mut buf, dns::TYPE_A, &address);
+        buf
+    }
+
+    // A NetBIOS name registration broadcast, with the NB flags in the additional record
+    fn nbns_registration(name: &str, nb_flags: u16) -> Vec<u8> {
+        let mut buf = dns::build_query(
+            1,
+            (NBNS_OPCODE_REGISTRATION << 11) | 0x0110,
+            &dns::encode_netbios_name(name, 0x00),
+            dns::TYPE_NB,
+            dns::CLASS_IN,
+        );
+        buf[10..12].copy_from_slice(&1u16.to_be_bytes());
+        buf.extend_from_slice(&[0xC0, 0x0C]);
+        let mut rdata = nb_flags.to_be_bytes().to_vec();
+        rdata.extend_from_slice(&[192, 168, 1, 20]);
+        record(&mut buf, dns::TYPE_NB, &rdata);
+        buf
+    }
+
+    fn dhcp_request(options: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8; 240];
+        buf[0] = 1;
+        buf[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE);
+        buf.extend_from_slice(options);
+        buf
+    }
+
+    #[test]
+    fn mdns_announcement_names_its_sender() {
+        let announcement = mdns_announcement([192, 168, 1, 20]);
+        assert_eq!(
+            hostname(MDNS_PORT, MDNS_PORT, &announcement).as_deref(),
+            Some("printer")
+        );
+        // Answering for another host says nothing about the sender
+        let other = mdns_announcement([192, 168, 1, 21]);
+        assert_eq!(hostname(MDNS_PORT, MDNS_PORT, &other), None);
+    }
+
+    #[test]
+    fn nbns_registration_names_workstation_but_not_group() {
+        let unique = nbns_registration("DESKTOP-1", 0x0000);
+        assert_eq!(
+            hostname(NBNS_PORT, NBNS_PORT, &unique).as_deref(),
+            Some("DESKTOP-1")
+        );
+        let group = nbns_registration("WORKGROUP", NB_FLAG_GROUP);
+        assert_eq!(hostname(NBNS_PORT, NBNS_PORT, &group), None);
+    }
+
+    #[test]
+    fn dhcp_request_hostname_option() {
+        // Message type, padding, then the hostname
+        let request = dhcp_request(&[53, 1, 3, 0, 12, 6, b'l', b'a', b'p', b't', b'o', b'p', 255]);
+        assert_eq!(
+            hostname(DHCP_CLIENT_PORT, DHCP_SERVER_PORT, &request).as_deref(),
+            Some("laptop")
+        );
+        // Option length running past the end of the packet
+        let truncated = dhcp_request(&[12, 40, b'l', b'a']);
+        assert_eq!(
+            hostname(DHCP_CLIENT_PORT, DHCP_SERVER_PORT, &truncated),
+            None
+        );
+    }
+}
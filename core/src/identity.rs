@@ -0,0 +1,74 @@
+use crate::models::{DeviceId, DeviceTable};
+use crate::oui;
+use pnet::datalink::MacAddr;
+use std::net::IpAddr;
+
+// Identity for a newly seen device. A randomized MAC says nothing about the
+// hardware behind it, so when the device has already told us its name we key it
+// by that and can recognise it again after the MAC rotates.
+pub fn device_id(mac: MacAddr, hostname: Option<&str>) -> DeviceId {
+    match hostname {
+        Some(name) if oui::is_locally_administered(mac) => fingerprint(name),
+        _ => DeviceId::Mac(mac.to_string()),
+    }
+}
+
+fn fingerprint(hostname: &str) -> DeviceId {
+    DeviceId::Fingerprint(hostname.to_lowercase())
+}
+
+// Hosts behind proxy ARP carry the router's MAC without being the router, so they
+// never match
+pub fn find_by_mac(devices: &DeviceTable, mac_address: &str) -> Option<DeviceId> {
+    devices
+        .iter()
+        .find(|d| d.mac_address == mac_address && !matches!(d.key(), DeviceId::Proxied(_)))
+        .map(|d| d.key().clone())
+}
+
+pub fn find_by_ip(devices: &DeviceTable, ip: IpAddr) -> Option<DeviceId> {
+    devices
+        .iter()
+        .find(|d| d.has_address(ip))
+        .map(|d| d.key().clone())
+}
+
+// Called when the device behind a randomized MAC announces its name. The entry is
+// re-keyed by that name, merging into the one left behind by an earlier MAC if
+// there is one. Returns the id the device ends up under.
+pub fn claim_fingerprint(
+    devices: &DeviceTable,
+    id: &DeviceId,
+    mac: MacAddr,
+    hostname: &str,
+) -> DeviceId {
+    let target = fingerprint(hostname);
+    if !oui::is_locally_administered(mac) || *id == target {
+        return id.clone();
+    }
+    let Some((_, mut device)) = devices.remove(id) else {
+        return id.clone();
+    };
+    device.id = target.clone();
+    match devices.get_mut(&target) {
+        Some(mut existing) => existing.absorb(device),
+        None => {
+            devices.insert(target.clone(), device);
+        }
+    }
+    target
+}
+
+// A DHCP client without a lease yet sends from 0.0.0.0 with nothing but its MAC
+// and name. If that name belongs to a device we track under an older randomized
+// MAC, move it over so the frames that follow land on the same entry.
+pub fn adopt_mac(devices: &DeviceTable, mac: MacAddr, hostname: &str) -> Option<DeviceId> {
+    if !oui::is_locally_administered(mac) {
+        return None;
+    }
+    let target = fingerprint(hostname);
+    let mut device = devices.get_mut(&target)?;
+    device.mac_address = mac.to_string();
+    device.vendor = oui::lookup_vendor(mac);
+    Some(target)
+}
@@ -0,0 +1,504 @@
+use crate::identity;
+use crate::models::{parse_tags, DeviceId, DeviceStatus, DeviceTable, NetworkDevice, PortState};
+use crate::oui;
+use anyhow::Result;
+use chrono::Utc;
+use pnet::datalink::MacAddr;
+use std::collections::btree_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    // Asset list with a header row, including our own CSV export
+    Csv,
+    // dnsmasq.leases or ISC dhcpd.leases
+    DhcpLeases,
+    // nmap -oX output
+    NmapXml,
+}
+
+impl ImportFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            ImportFormat::Csv => "CSV",
+            ImportFormat::DhcpLeases => "DHCP leases",
+            ImportFormat::NmapXml => "nmap",
+        }
+    }
+
+    // Goes by the file extension first and falls back to sniffing the content
+    pub fn detect(path: &Path, text: &str) -> Self {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let head = text.trim_start();
+        if extension == "xml" || head.starts_with("<?xml") || head.contains("<nmaprun") {
+            ImportFormat::NmapXml
+        } else if extension == "leases" || looks_like_leases(head) {
+            ImportFormat::DhcpLeases
+        } else {
+            ImportFormat::Csv
+        }
+    }
+}
+
+// dnsmasq lines start with a numeric expiry time, ISC files with comments or lease blocks
+fn looks_like_leases(text: &str) -> bool {
+    let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    first.starts_with('#')
+        || first.starts_with("lease ")
+        || (first.split_whitespace().count() >= 4
+            && first
+                .split_whitespace()
+                .next()
+                .is_some_and(|t| t.parse::<u64>().is_ok()))
+}
+
+// What one source knows about one device. Every field is optional.
+#[derive(Debug, Clone, Default)]
+pub struct ImportedRecord {
+    pub mac: Option<MacAddr>,
+    pub ip: Option<IpAddr>,
+    pub hostname: Option<String>,
+    pub vendor: Option<String>,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    pub ports: Vec<(u16, PortState)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub updated: usize,
+    // Records with no MAC that didn't match a known address
+    pub skipped: usize,
+}
+
+pub fn parse(format: ImportFormat, text: &str) -> Result<Vec<ImportedRecord>> {
+    match format {
+        ImportFormat::Csv => parse_csv(text),
+        ImportFormat::DhcpLeases => Ok(parse_leases(text)),
+        ImportFormat::NmapXml => parse_nmap(text),
+    }
+}
+
+// Reads a file, detects its format and merges it into the table. Fields it sets
+// are labelled "<format> (<file name>)".
+pub fn import_file(devices: &DeviceTable, path: &Path) -> Result<ImportSummary> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let format = ImportFormat::detect(path, &text);
+    let records = parse(format, &text)?;
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let source = format!("{} ({})", format.label(), file_name);
+    Ok(merge(devices, records, &source))
+}
+
+// Matches each record to a device by MAC, or by IP when it has none. Imported
+// data fills gaps and never overrides what live discovery or the user already set.
+pub fn merge(devices: &DeviceTable, records: Vec<ImportedRecord>, source: &str) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+    for record in records {
+        // A MAC we don't know is another machine, whoever holds its old address now
+        let existing = match record.mac {
+            Some(mac) => identity::find_by_mac(devices, &mac.to_string()),
+            None => record.ip.and_then(|ip| identity::find_by_ip(devices, ip)),
+        };
+
+        let id = match (existing, record.mac) {
+            (Some(id), _) => {
+                summary.updated += 1;
+                id
+            }
+            (None, Some(mac)) => {
+                let id = DeviceId::Mac(mac.to_string());
+                let mut device =
+                    NetworkDevice::new(id.clone(), mac.to_string(), oui::lookup_vendor(mac));
+                // Known from paperwork only until it shows up on the wire
+                device.status = DeviceStatus::Unknown;
+                devices.insert(id.clone(), device);
+                summary.added += 1;
+                id
+            }
+            (None, None) => {
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        if let Some(mut device) = devices.get_mut(&id) {
+            apply(&mut device, record, source);
+        }
+    }
+    summary
+}
+
+fn apply(device: &mut NetworkDevice, record: ImportedRecord, source: &str) {
+    let label = |device: &mut NetworkDevice, field: &str| {
+        device
+            .field_sources
+            .insert(field.to_string(), source.to_string());
+    };
+
+    if let Some(ip) = record.ip {
+        if !device.has_address(ip) && device.ip_address.is_empty() {
+            device.record_address(ip, Utc::now());
+            label(device, "ip_address");
+        }
+    }
+    if let Some(hostname) = record.hostname {
+        // A hostname from an earlier import may be replaced, a discovered one may not
+        if device.hostname.is_empty() || device.field_sources.contains_key("hostname") {
+            device.hostname = hostname;
+            label(device, "hostname");
+        }
+    }
+    if let Some(vendor) = record.vendor {
+        if device.vendor.is_empty() {
+            device.vendor = vendor;
+            label(device, "vendor");
+        }
+    }
+    if let Some(name) = record.name {
+        if device.labels.name.is_empty() {
+            device.labels.name = name;
+            label(device, "name");
+        }
+    }
+    if !record.tags.is_empty() {
+        let before = device.labels.tags.len();
+        for tag in record.tags {
+            if !device.labels.tags.contains(&tag) {
+                device.labels.tags.push(tag);
+            }
+        }
+        if device.labels.tags.len() > before {
+            label(device, "tags");
+        }
+    }
+    if let Some(notes) = record.notes {
+        if device.labels.notes.is_empty() {
+            device.labels.notes = notes;
+            label(device, "notes");
+        }
+    }
+    let mut added_ports = false;
+    for (port, state) in record.ports {
+        if let Entry::Vacant(entry) = device.ports.entry(port) {
+            entry.insert(state);
+            added_ports = true;
+        }
+    }
+    if added_ports {
+        label(device, "ports");
+    }
+}
+
+fn parse_mac(text: &str) -> Option<MacAddr> {
+    text.trim().replace('-', ":").parse().ok()
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+// Column names are matched loosely so hand-made spreadsheets work as well as our own export
+fn parse_csv(text: &str) -> Result<Vec<ImportedRecord>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.to_lowercase().replace([' ', '-'], "_"))
+        .collect();
+    let column = |names: &[&str]| headers.iter().position(|h| names.contains(&h.as_str()));
+    let ip_column = column(&["ip", "ip_address", "address", "ipv4"]);
+    let mac_column = column(&["mac", "mac_address", "hardware_address"]);
+    if ip_column.is_none() && mac_column.is_none() {
+        return Err(anyhow::anyhow!(
+            "CSV needs an IP or MAC column, found: {}",
+            headers.join(", ")
+        ));
+    }
+    let hostname_column = column(&["hostname", "host", "dns_name"]);
+    let vendor_column = column(&["vendor", "manufacturer"]);
+    let name_column = column(&["name", "device", "device_name", "asset", "label"]);
+    let tags_column = column(&["tags", "tag", "category"]);
+    let notes_column = column(&["notes", "note", "comment", "comments", "description"]);
+    let ports_column = column(&["open_ports", "ports"]);
+
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row?;
+        let field = |column: Option<usize>| column.and_then(|i| row.get(i)).unwrap_or("");
+        let record = ImportedRecord {
+            mac: parse_mac(field(mac_column)),
+            ip: field(ip_column).parse().ok(),
+            hostname: non_empty(field(hostname_column)),
+            vendor: non_empty(field(vendor_column)),
+            name: non_empty(field(name_column)),
+            tags: parse_tags(field(tags_column)),
+            notes: non_empty(field(notes_column)),
+            ports: field(ports_column)
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter_map(|p| p.parse().ok())
+                .map(|port| (port, PortState::Open))
+                .collect(),
+        };
+        if record.mac.is_some() || record.ip.is_some() {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+fn parse_leases(text: &str) -> Vec<ImportedRecord> {
+    if text.lines().any(|l| l.trim_start().starts_with("lease ")) {
+        parse_isc_leases(text)
+    } else {
+        parse_dnsmasq_leases(text)
+    }
+}
+
+// "<expiry> <mac> <ip> <hostname or *> <client id>"
+fn parse_dnsmasq_leases(text: &str) -> Vec<ImportedRecord> {
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            Some(ImportedRecord {
+                mac: Some(parse_mac(fields[1])?),
+                ip: fields[2].parse().ok(),
+                hostname: (fields[3] != "*").then(|| fields[3].to_string()),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+// dhcpd appends a new block each time a lease changes, so the last block for an
+// address is the current one
+fn parse_isc_leases(text: &str) -> Vec<ImportedRecord> {
+    let mut leases: Vec<ImportedRecord> = Vec::new();
+    let mut by_ip: HashMap<IpAddr, usize> = HashMap::new();
+    let mut current: Option<ImportedRecord> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("lease ") {
+            current = Some(ImportedRecord {
+                ip: rest.trim_end_matches('{').trim().parse().ok(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(lease) = current.as_mut() else {
+            continue;
+        };
+        if line == "}" {
+            let lease = current.take().unwrap();
+            match lease.ip.and_then(|ip| by_ip.get(&ip).copied()) {
+                Some(index) => leases[index] = lease,
+                None => {
+                    if let Some(ip) = lease.ip {
+                        by_ip.insert(ip, leases.len());
+                    }
+                    leases.push(lease);
+                }
+            }
+        } else if let Some(mac) = line.strip_prefix("hardware ethernet ") {
+            lease.mac = parse_mac(mac.trim_end_matches(';'));
+        } else if let Some(name) = line.strip_prefix("client-hostname ") {
+            lease.hostname = non_empty(name.trim_end_matches(';').trim_matches('"'));
+        }
+    }
+    leases.retain(|lease| lease.mac.is_some());
+    leases
+}
+
+fn parse_nmap(text: &str) -> Result<Vec<ImportedRecord>> {
+    // nmap always writes a <!DOCTYPE nmaprun> header
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let document = roxmltree::Document::parse_with_options(text, options)
+        .map_err(|e| anyhow::anyhow!("Invalid nmap XML: {}", e))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "nmaprun" {
+        return Err(anyhow::anyhow!("Not an nmap XML report"));
+    }
+
+    let mut records = Vec::new();
+    for host in root.children().filter(|n| n.has_tag_name("host")) {
+        let is_up = host
+            .children()
+            .find(|n| n.has_tag_name("status"))
+            .and_then(|n| n.attribute("state"))
+            .map(|state| state == "up")
+            .unwrap_or(true);
+        if !is_up {
+            continue;
+        }
+
+        let mut record = ImportedRecord::default();
+        for address in host.children().filter(|n| n.has_tag_name("address")) {
+            let addr = address.attribute("addr").unwrap_or("");
+            match address.attribute("addrtype") {
+                Some("mac") => {
+                    record.mac = parse_mac(addr);
+                    record.vendor = address.attribute("vendor").and_then(non_empty);
+                }
+                // Keep the IPv4 address if nmap reports both families
+                Some("ipv4") => record.ip = addr.parse().ok(),
+                Some("ipv6") if record.ip.is_none() => record.ip = addr.parse().ok(),
+                _ => {}
+            }
+        }
+        record.hostname = host
+            .descendants()
+            .filter(|n| n.has_tag_name("hostname"))
+            .filter_map(|n| n.attribute("name"))
+            .find_map(non_empty);
+        for port in host.descendants().filter(|n| n.has_tag_name("port")) {
+            if port.attribute("protocol") != Some("tcp") {
+                continue;
+            }
+            let Some(number) = port.attribute("portid").and_then(|p| p.parse().ok()) else {
+                continue;
+            };
+            let state = port
+                .children()
+                .find(|n| n.has_tag_name("state"))
+                .and_then(|n| n.attribute("state"));
+            let state = match state {
+                Some("open") => PortState::Open,
+                Some("closed") => PortState::Closed,
+                Some(s) if s.contains("filtered") => PortState::Filtered,
+                _ => continue,
+            };
+            record.ports.push((number, state));
+        }
+        if record.mac.is_some() || record.ip.is_some() {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dashmap::DashMap;
+
+    const NMAP_REPORT: &str = include_str!("../tests/fixtures/nmap.xml");
+
+    fn device(mac: &str, ip: &str) -> NetworkDevice {
+        let mut device = NetworkDevice::new(
+            DeviceId::Mac(mac.to_string()),
+            mac.to_string(),
+            String::new(),
+        );
+        device.record_address(ip.parse().unwrap(), Utc::now());
+        device
+    }
+
+    #[test]
+    fn parses_nmap_report_with_doctype() {
+        let path = Path::new("scan.xml");
+        assert_eq!(
+            ImportFormat::detect(path, NMAP_REPORT),
+            ImportFormat::NmapXml
+        );
+        let records = parse(ImportFormat::NmapXml, NMAP_REPORT).unwrap();
+
+        // The host that was down is left out
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(
+            record.mac,
+            Some(MacAddr(0xb8, 0x27, 0xeb, 0x12, 0x34, 0x56))
+        );
+        assert_eq!(record.ip, Some("192.168.1.20".parse().unwrap()));
+        assert_eq!(record.hostname.as_deref(), Some("pi.lan"));
+        assert_eq!(record.vendor.as_deref(), Some("Raspberry Pi Foundation"));
+        assert_eq!(
+            record.ports,
+            vec![
+                (22, PortState::Open),
+                (80, PortState::Filtered),
+                (443, PortState::Closed),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_mac_is_not_merged_by_ip() {
+        let devices: DeviceTable = DashMap::new();
+        let live = device("00:11:22:33:44:55", "192.168.1.20");
+        devices.insert(live.id.clone(), live);
+
+        // A stale lease for another machine that used to have the address
+        let summary = merge(
+            &devices,
+            vec![ImportedRecord {
+                mac: parse_mac("00:11:22:33:44:66"),
+                ip: "192.168.1.20".parse().ok(),
+                hostname: Some("old-laptop".to_string()),
+                ..Default::default()
+            }],
+            "test",
+        );
+
+        assert_eq!(summary.added, 1);
+        assert_eq!(summary.updated, 0);
+        let live = devices
+            .get(&DeviceId::Mac("00:11:22:33:44:55".to_string()))
+            .unwrap();
+        assert!(live.hostname.is_empty());
+        let stale = devices
+            .get(&DeviceId::Mac("00:11:22:33:44:66".to_string()))
+            .unwrap();
+        assert_eq!(stale.hostname, "old-laptop");
+        assert_eq!(stale.status, DeviceStatus::Unknown);
+    }
+
+    #[test]
+    fn record_without_mac_is_merged_by_ip() {
+        let devices: DeviceTable = DashMap::new();
+        let live = device("00:11:22:33:44:55", "192.168.1.20");
+        devices.insert(live.id.clone(), live);
+
+        let summary = merge(
+            &devices,
+            vec![ImportedRecord {
+                ip: "192.168.1.20".parse().ok(),
+                name: Some("Printer".to_string()),
+                ..Default::default()
+            }],
+            "test",
+        );
+
+        assert_eq!(summary.updated, 1);
+        assert_eq!(devices.len(), 1);
+        let live = devices
+            .get(&DeviceId::Mac("00:11:22:33:44:55".to_string()))
+            .unwrap();
+        assert_eq!(live.labels.name, "Printer");
+    }
+}
@@ -0,0 +1,185 @@
+use crate::config::AppConfig;
+use crate::models::{DeviceStatus, DeviceTable, NetworkDevice};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+// Bump this and append a step to MIGRATIONS whenever the stored layout changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+// MIGRATIONS[n] upgrades a version n + 1 document to version n + 2 in place
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+#[derive(Serialize, Deserialize)]
+struct InventoryFile {
+    version: u32,
+    devices: Vec<NetworkDevice>,
+}
+
+// Reads the saved device table. A missing file is an empty inventory. Devices come
+// back inactive since nothing has been heard from them in this session yet.
+pub fn load(path: &Path) -> Result<Vec<NetworkDevice>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(path)?;
+    let mut document: Value = serde_json::from_str(&text)
+        .map_err(|e| anyhow::anyhow!("Invalid inventory {}: {}", path.display(), e))?;
+    migrate(&mut document)?;
+    let file: InventoryFile = serde_json::from_value(document)
+        .map_err(|e| anyhow::anyhow!("Invalid inventory {}: {}", path.display(), e))?;
+
+    let mut devices = file.devices;
+    for device in &mut devices {
+        device.status = DeviceStatus::Inactive;
+    }
+    Ok(devices)
+}
+
+// The interface's own inventory. The shared file every interface wrote to before
+// they were kept apart goes to whichever interface loads first, the others start
+// out empty rather than with another network's devices.
+pub fn load_for_interface(config: &AppConfig, interface: &str) -> Result<Vec<NetworkDevice>> {
+    let path = config.interface_inventory_path(interface);
+    let shared = config.inventory_path();
+    if !path.exists() && shared.exists() {
+        info!(from = %shared.display(), to = %path.display(), "Moving shared inventory");
+        fs::rename(&shared, &path).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to move {} to {}: {}",
+                shared.display(),
+                path.display(),
+                e
+            )
+        })?;
+    }
+    load(&path)
+}
+
+fn migrate(document: &mut Value) -> Result<()> {
+    let version = document
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| anyhow::anyhow!("Inventory has no schema version"))?
+        as u32;
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "Inventory schema version {} is not supported (expected at most {})",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+    for step in &MIGRATIONS[version as usize - 1..] {
+        step(document);
+    }
+    document["version"] = SCHEMA_VERSION.into();
+    Ok(())
+}
+
+// Writes to a temporary file first so a crash mid-write can't truncate the inventory
+pub fn save(path: &Path, devices: &DeviceTable) -> Result<()> {
+    let mut devices: Vec<NetworkDevice> = devices.iter().map(|d| d.value().clone()).collect();
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+    let file = InventoryFile {
+        version: SCHEMA_VERSION,
+        devices,
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, serde_json::to_string_pretty(&file)?)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DeviceId;
+    use dashmap::DashMap;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ndm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn saved_devices_load_back_inactive() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join("inventory.json");
+        let id = DeviceId::Mac("00:11:22:33:44:55".to_string());
+        let mut device =
+            NetworkDevice::new(id.clone(), "00:11:22:33:44:55".to_string(), String::new());
+        let seen = "2024-03-01T12:00:00Z".parse().unwrap();
+        device.record_address("192.168.1.20".parse().unwrap(), seen);
+        device.record_address("192.168.1.21".parse().unwrap(), seen);
+        device.first_seen = seen;
+        device.last_seen = seen;
+        device.labels.name = "Printer".to_string();
+        device.labels.tags = vec!["office".to_string()];
+        device.labels.notes = "2nd floor".to_string();
+        let devices: DeviceTable = DashMap::new();
+        devices.insert(id.clone(), device.clone());
+
+        save(&path, &devices).unwrap();
+        let loaded = load(&path).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        let loaded = &loaded[0];
+        assert_eq!(loaded.id, id);
+        assert_eq!(loaded.status, DeviceStatus::Inactive);
+        assert_eq!(loaded.first_seen, seen);
+        assert_eq!(loaded.last_seen, seen);
+        assert_eq!(loaded.ip_address, "192.168.1.21");
+        assert_eq!(loaded.ip_history, device.ip_history);
+        assert_eq!(loaded.labels.name, "Printer");
+        assert_eq!(loaded.labels.tags, vec!["office".to_string()]);
+        assert_eq!(loaded.labels.notes, "2nd floor");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unsupported_schema_versions() {
+        for version in [0, SCHEMA_VERSION + 1] {
+            let mut document = serde_json::json!({ "version": version, "devices": [] });
+            assert!(migrate(&mut document).is_err());
+        }
+        let mut unversioned = serde_json::json!({ "devices": [] });
+        assert!(migrate(&mut unversioned).is_err());
+        let mut current = serde_json::json!({ "version": SCHEMA_VERSION, "devices": [] });
+        migrate(&mut current).unwrap();
+    }
+
+    #[test]
+    fn shared_inventory_moves_to_first_interface_only() {
+        let dir = temp_dir("inventory");
+        let config = AppConfig {
+            inventory_path: Some(dir.join("inventory.json")),
+            ..Default::default()
+        };
+        let devices: DeviceTable = DashMap::new();
+        let id = DeviceId::Mac("00:11:22:33:44:55".to_string());
+        devices.insert(
+            id.clone(),
+            NetworkDevice::new(id.clone(), "00:11:22:33:44:55".to_string(), String::new()),
+        );
+        save(&config.inventory_path(), &devices).unwrap();
+
+        let eth0 = load_for_interface(&config, "eth0").unwrap();
+        assert_eq!(eth0.len(), 1);
+        assert_eq!(eth0[0].id, id);
+        assert!(!config.inventory_path().exists());
+        assert!(config.interface_inventory_path("eth0").exists());
+
+        assert!(load_for_interface(&config, "wlan0").unwrap().is_empty());
+        assert_eq!(load_for_interface(&config, "eth0").unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
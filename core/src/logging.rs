@@ -0,0 +1,62 @@
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+pub const DEFAULT_LEVEL: &str = "info";
+// A week of daily files
+const KEPT_LOG_FILES: usize = 7;
+
+static FILTER: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
+
+// Logs to stderr and as JSON lines to a file in `dir` that rolls over daily. Lines
+// still buffered are lost once the returned guard is dropped, so hold on to it
+// until exit.
+pub fn init(dir: &Path, name: &str, level: &str) -> Result<WorkerGuard> {
+    let (filter, handle) = reload::Layer::new(parse_filter(level)?);
+    fs::create_dir_all(dir)?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(name)
+        .filename_suffix("log")
+        .max_log_files(KEPT_LOG_FILES)
+        .build(dir)
+        .map_err(|e| anyhow::anyhow!("Failed to open log in {}: {}", dir.display(), e))?;
+    let (file, guard) = tracing_appender::non_blocking(appender);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            fmt::layer()
+                .with_ansi(io::stderr().is_terminal())
+                .with_writer(io::stderr),
+        )
+        .with(fmt::layer().json().with_writer(file))
+        .try_init()?;
+    let _ = FILTER.set(handle);
+    Ok(guard)
+}
+
+// Takes the same levels and directives as `init`
+pub fn set_level(level: &str) -> Result<()> {
+    let handle = FILTER
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("Logging is not set up"))?;
+    handle.reload(parse_filter(level)?)?;
+    tracing::info!(level, "Log level changed");
+    Ok(())
+}
+
+pub fn level() -> Option<String> {
+    FILTER.get()?.with_current(|filter| filter.to_string()).ok()
+}
+
+fn parse_filter(level: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(level).map_err(|e| anyhow::anyhow!("Invalid log level '{}': {}", level, e))
+}
@@ -0,0 +1,270 @@
+use crate::models::DeviceTable;
+use crate::packet_io::{FrameReceiver, FrameSender, PacketIo};
+use anyhow::Result;
+use chrono::Utc;
+use pnet::datalink::NetworkInterface;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Upper bounds of the scan duration histogram, in seconds
+const SCAN_BUCKETS: [f64; 8] = [1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+#[derive(Default)]
+struct ScanTimes {
+    // Scans that took at most the matching SCAN_BUCKETS entry
+    buckets: [u64; SCAN_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+// Counters a session keeps for the daemon's /metrics endpoint
+#[derive(Default)]
+pub struct Metrics {
+    frames_received: AtomicU64,
+    frames_sent: AtomicU64,
+    send_errors: AtomicU64,
+    scans: Mutex<BTreeMap<String, ScanTimes>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_scan(&self, profile: &str, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut scans = self.scans.lock().unwrap();
+        let times = scans.entry(profile.to_string()).or_default();
+        for (bucket, bound) in times.buckets.iter_mut().zip(SCAN_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        times.count += 1;
+        times.sum += seconds;
+    }
+
+    // Prometheus text exposition of the counters and the current device table
+    pub fn render(&self, devices: &DeviceTable) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "ndm_devices",
+            "gauge",
+            "Tracked devices by status",
+        );
+        let mut by_status: BTreeMap<&str, usize> = ["active", "inactive", "blocked", "unknown"]
+            .into_iter()
+            .map(|status| (status, 0))
+            .collect();
+        for device in devices.iter() {
+            *by_status.entry(device.status_name()).or_default() += 1;
+        }
+        for (status, count) in by_status {
+            let _ = writeln!(out, "ndm_devices{{status=\"{}\"}} {}", status, count);
+        }
+
+        let counters = [
+            (
+                "ndm_frames_received_total",
+                "Frames received by the scanner",
+                &self.frames_received,
+            ),
+            (
+                "ndm_frames_sent_total",
+                "Frames sent by the scanner",
+                &self.frames_sent,
+            ),
+            (
+                "ndm_probe_send_errors_total",
+                "Probes the scanner failed to send",
+                &self.send_errors,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+
+        header(
+            &mut out,
+            "ndm_scan_duration_seconds",
+            "histogram",
+            "Time from sending a scan's first probe until its results settled",
+        );
+        for (profile, times) in self.scans.lock().unwrap().iter() {
+            let profile = escape(profile);
+            for (bound, count) in SCAN_BUCKETS.iter().zip(times.buckets) {
+                let _ = writeln!(
+                    out,
+                    "ndm_scan_duration_seconds_bucket{{profile=\"{}\",le=\"{}\"}} {}",
+                    profile, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "ndm_scan_duration_seconds_bucket{{profile=\"{}\",le=\"+Inf\"}} {}",
+                profile, times.count
+            );
+            let _ = writeln!(
+                out,
+                "ndm_scan_duration_seconds_sum{{profile=\"{}\"}} {}",
+                profile, times.sum
+            );
+            let _ = writeln!(
+                out,
+                "ndm_scan_duration_seconds_count{{profile=\"{}\"}} {}",
+                profile, times.count
+            );
+        }
+
+        // Keyed by id alone so a new address or name doesn't start a new series.
+        // The info gauge carries those for joining on id.
+        let now = Utc::now();
+        let mut devices: Vec<_> = devices.iter().map(|d| d.value().clone()).collect();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        header(
+            &mut out,
+            "ndm_device_last_seen_age_seconds",
+            "gauge",
+            "Seconds since each device was last seen",
+        );
+        for device in &devices {
+            let age = (now - device.last_seen).num_milliseconds().max(0) as f64 / 1000.0;
+            let _ = writeln!(
+                out,
+                "ndm_device_last_seen_age_seconds{{id=\"{}\"}} {}",
+                escape(&device.id.to_string()),
+                age
+            );
+        }
+        header(
+            &mut out,
+            "ndm_device_info",
+            "gauge",
+            "Current address, MAC and name of each device, always 1",
+        );
+        for device in &devices {
+            let _ = writeln!(
+                out,
+                "ndm_device_info{{id=\"{}\",ip=\"{}\",mac=\"{}\",name=\"{}\"}} 1",
+                escape(&device.id.to_string()),
+                escape(&device.ip_address),
+                escape(&device.mac_address),
+                escape(device.display_name())
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Wraps another backend and counts the frames going through it
+pub struct CountingPacketIo {
+    inner: Arc<dyn PacketIo>,
+    metrics: Arc<Metrics>,
+}
+
+impl CountingPacketIo {
+    pub fn new(inner: Arc<dyn PacketIo>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+impl PacketIo for CountingPacketIo {
+    fn open(
+        &self,
+        interface: &NetworkInterface,
+    ) -> Result<(Box<dyn FrameSender>, Box<dyn FrameReceiver>)> {
+        let (tx, rx) = self.inner.open(interface)?;
+        Ok((
+            Box::new(CountingSender {
+                inner: tx,
+                metrics: self.metrics.clone(),
+            }),
+            Box::new(CountingReceiver {
+                inner: rx,
+                metrics: self.metrics.clone(),
+            }),
+        ))
+    }
+}
+
+struct CountingSender {
+    inner: Box<dyn FrameSender>,
+    metrics: Arc<Metrics>,
+}
+
+impl FrameSender for CountingSender {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        let result = self.inner.send_frame(frame);
+        let counter = match result {
+            Ok(()) => &self.metrics.frames_sent,
+            Err(_) => &self.metrics.send_errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+}
+
+struct CountingReceiver {
+    inner: Box<dyn FrameReceiver>,
+    metrics: Arc<Metrics>,
+}
+
+impl FrameReceiver for CountingReceiver {
+    fn next_frame(&mut self) -> io::Result<&[u8]> {
+        let frame = self.inner.next_frame()?;
+        self.metrics.frames_received.fetch_add(1, Ordering::Relaxed);
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DeviceId, NetworkDevice};
+    use dashmap::DashMap;
+
+    #[test]
+    fn device_series_are_keyed_by_id() {
+        let devices: DeviceTable = DashMap::new();
+        let id = DeviceId::Mac("00:11:22:33:44:55".to_string());
+        let mut device =
+            NetworkDevice::new(id.clone(), "00:11:22:33:44:55".to_string(), String::new());
+        device.record_address("192.168.1.20".parse().unwrap(), Utc::now());
+        device.labels.name = "Front \"desk\"".to_string();
+        devices.insert(id.clone(), device);
+
+        let rendered = Metrics::new().render(&devices);
+        assert!(rendered.contains("ndm_device_last_seen_age_seconds{id=\"00:11:22:33:44:55\"} "));
+        assert!(rendered.contains(
+            "ndm_device_info{id=\"00:11:22:33:44:55\",ip=\"192.168.1.20\",\
+             mac=\"00:11:22:33:44:55\",name=\"Front \\\"desk\\\"\"} 1"
+        ));
+        assert!(rendered.contains("ndm_devices{status=\"active\"} 1"));
+
+        // Renaming the device only changes the info series
+        devices.get_mut(&id).unwrap().labels.name = "Printer".to_string();
+        let renamed = Metrics::new().render(&devices);
+        assert!(renamed.contains("ndm_device_last_seen_age_seconds{id=\"00:11:22:33:44:55\"} "));
+        assert!(renamed.contains("name=\"Printer\"} 1"));
+    }
+}
@@ -0,0 +1,355 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+
+// Enum to represent the status of a device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeviceStatus {
+    Active,
+    Inactive,
+    Blocked,
+    Unknown,
+}
+
+// State of a TCP port as seen from the replies to our SYN probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PortState {
+    Open,
+    Closed,
+    Filtered,
+}
+
+// Stable identity of a physical device. Most hardware is keyed by its MAC, but
+// randomized (locally administered) MACs rotate, so those devices are keyed by
+// the name they announce for themselves once we have heard it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DeviceId {
+    Mac(String),
+    Fingerprint(String),
+    // Hosts we only see through the router's proxy ARP all share its MAC, so
+    // they're keyed by their address
+    Proxied(String),
+}
+
+const PROXIED_PREFIX: &str = "proxied@";
+
+// IPv6 privacy addresses rotate daily, so only the most recently seen addresses
+// are kept rather than every one a device has ever used
+const MAX_IP_HISTORY: usize = 32;
+const MAX_IPV6_ADDRESSES: usize = 8;
+
+impl DeviceId {
+    // Reverses `to_string`. Fingerprints are hostnames, which never contain a colon.
+    pub fn parse(text: &str) -> Self {
+        if let Some(address) = text.strip_prefix(PROXIED_PREFIX) {
+            DeviceId::Proxied(address.to_lowercase())
+        } else if text.contains(':') {
+            DeviceId::Mac(text.to_lowercase())
+        } else {
+            DeviceId::Fingerprint(text.to_lowercase())
+        }
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceId::Mac(mac) => f.write_str(mac),
+            DeviceId::Fingerprint(name) => f.write_str(name),
+            DeviceId::Proxied(address) => write!(f, "{}{}", PROXIED_PREFIX, address),
+        }
+    }
+}
+
+// An address a device has held and when we saw it using it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpRecord {
+    pub address: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+// Set by the user rather than learned from the network
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceLabels {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub notes: String,
+}
+
+// Splits "printer, 2nd floor,guest" into trimmed tags, dropping blanks and repeats
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+// The device table shared by the scanner, killer, resolver and UI
+pub type DeviceTable = DashMap<DeviceId, NetworkDevice>;
+
+// Struct to hold information about a network device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkDevice {
+    pub id: DeviceId,
+    // Most recent IPv4 address, or the first IPv6 one for IPv6-only hosts
+    pub ip_address: String,
+    // Link-local and global IPv6 addresses seen from this device's MAC
+    #[serde(default)]
+    pub ipv6_addresses: Vec<String>,
+    pub hostname: String,
+    pub mac_address: String,
+    pub vendor: String,
+    pub status: DeviceStatus,
+    // Frames from off-link hosts that were routed through this device (gateway only)
+    #[serde(default)]
+    pub off_link_frames: u64,
+    #[serde(default)]
+    pub ports: BTreeMap<u16, PortState>,
+    // Every address this device has been seen with, oldest first
+    #[serde(default)]
+    pub ip_history: Vec<IpRecord>,
+    #[serde(default)]
+    pub labels: DeviceLabels,
+    // Which import supplied a field, e.g. "hostname" -> "nmap (scan.xml)". Fields
+    // learned from live traffic have no entry.
+    #[serde(default)]
+    pub field_sources: BTreeMap<String, String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    #[serde(skip)]
+    pub is_killed: bool,
+}
+
+impl NetworkDevice {
+    pub fn new(id: DeviceId, mac_address: String, vendor: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            ip_address: String::new(),
+            ipv6_addresses: Vec::new(),
+            hostname: String::new(),
+            mac_address,
+            vendor,
+            status: DeviceStatus::Active,
+            off_link_frames: 0,
+            ports: BTreeMap::new(),
+            ip_history: Vec::new(),
+            labels: DeviceLabels::default(),
+            field_sources: BTreeMap::new(),
+            first_seen: now,
+            last_seen: now,
+            is_killed: false,
+        }
+    }
+
+    // Notes that the device is using `ip` right now. Returns true the first time
+    // the address shows up in its history.
+    pub fn record_address(&mut self, ip: IpAddr, now: DateTime<Utc>) -> bool {
+        let address = ip.to_string();
+        let is_new = match self.ip_history.iter_mut().find(|r| r.address == address) {
+            Some(record) => {
+                record.last_seen = now;
+                false
+            }
+            None => {
+                self.ip_history.push(IpRecord {
+                    address: address.clone(),
+                    first_seen: now,
+                    last_seen: now,
+                });
+                true
+            }
+        };
+        match ip {
+            IpAddr::V4(_) => self.ip_address = address,
+            IpAddr::V6(_) => {
+                if !self.ipv6_addresses.contains(&address) {
+                    self.ipv6_addresses.push(address.clone());
+                }
+                if self.ip_address.is_empty() {
+                    self.ip_address = address;
+                }
+            }
+        }
+        self.trim_addresses();
+        is_new
+    }
+
+    // Drops whichever addresses were seen least recently once there are too many
+    fn trim_addresses(&mut self) {
+        while self.ip_history.len() > MAX_IP_HISTORY {
+            let oldest = (0..self.ip_history.len())
+                .min_by_key(|&i| self.ip_history[i].last_seen)
+                .unwrap();
+            self.ip_history.remove(oldest);
+        }
+        while self.ipv6_addresses.len() > MAX_IPV6_ADDRESSES {
+            // An address already gone from the history counts as the oldest
+            let last_seen = |address: &String| {
+                self.ip_history
+                    .iter()
+                    .find(|r| &r.address == address)
+                    .map(|r| r.last_seen)
+            };
+            let oldest = (0..self.ipv6_addresses.len())
+                .min_by_key(|&i| last_seen(&self.ipv6_addresses[i]))
+                .unwrap();
+            self.ipv6_addresses.remove(oldest);
+        }
+    }
+
+    // Folds another record of the same physical device into this one, keeping
+    // whichever is more recent for the current address and MAC
+    pub fn absorb(&mut self, other: NetworkDevice) {
+        for record in other.ip_history {
+            match self
+                .ip_history
+                .iter_mut()
+                .find(|r| r.address == record.address)
+            {
+                Some(existing) => {
+                    existing.first_seen = existing.first_seen.min(record.first_seen);
+                    existing.last_seen = existing.last_seen.max(record.last_seen);
+                }
+                None => self.ip_history.push(record),
+            }
+        }
+        self.ip_history.sort_by_key(|r| r.first_seen);
+        for address in other.ipv6_addresses {
+            if !self.ipv6_addresses.contains(&address) {
+                self.ipv6_addresses.push(address);
+            }
+        }
+        self.trim_addresses();
+        self.first_seen = self.first_seen.min(other.first_seen);
+        if other.last_seen > self.last_seen {
+            if !other.ip_address.is_empty() {
+                self.ip_address = other.ip_address;
+            }
+            self.mac_address = other.mac_address;
+            self.vendor = other.vendor;
+            self.status = other.status;
+            self.last_seen = other.last_seen;
+        }
+        if self.hostname.is_empty() {
+            self.hostname = other.hostname;
+        }
+        if self.labels.name.is_empty() {
+            self.labels.name = other.labels.name;
+        }
+        for tag in other.labels.tags {
+            if !self.labels.tags.contains(&tag) {
+                self.labels.tags.push(tag);
+            }
+        }
+        if self.labels.notes.is_empty() {
+            self.labels.notes = other.labels.notes;
+        }
+        self.ports.extend(other.ports);
+        for (field, source) in other.field_sources {
+            self.field_sources.entry(field).or_insert(source);
+        }
+        self.off_link_frames += other.off_link_frames;
+        self.is_killed |= other.is_killed;
+    }
+
+    // A name heard on the network replaces whatever an import said
+    pub fn set_hostname(&mut self, name: String) {
+        self.hostname = name;
+        self.field_sources.remove("hostname");
+    }
+
+    // The user's name for the device, falling back to what the network calls it
+    pub fn display_name(&self) -> &str {
+        if self.labels.name.is_empty() {
+            &self.hostname
+        } else {
+            &self.labels.name
+        }
+    }
+
+    // active, inactive, blocked or unknown, counting devices being cut off as blocked
+    pub fn status_name(&self) -> &'static str {
+        if self.is_killed {
+            return "blocked";
+        }
+        match self.status {
+            DeviceStatus::Active => "active",
+            DeviceStatus::Inactive => "inactive",
+            DeviceStatus::Blocked => "blocked",
+            DeviceStatus::Unknown => "unknown",
+        }
+    }
+
+    // Case-insensitive substring search over addresses, names, vendor, tags and notes
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+        let contains = |text: &str| text.to_lowercase().contains(&query);
+        contains(&self.ip_address)
+            || self.ipv6_addresses.iter().any(|a| contains(a))
+            || contains(&self.hostname)
+            || contains(&self.mac_address)
+            || contains(&self.vendor)
+            || contains(&self.labels.name)
+            || self.labels.tags.iter().any(|t| contains(t))
+            || contains(&self.labels.notes)
+    }
+
+    // Whether `ip` is one of the addresses the device currently holds
+    pub fn has_address(&self, ip: IpAddr) -> bool {
+        let address = ip.to_string();
+        self.ip_address == address || self.ipv6_addresses.contains(&address)
+    }
+
+    pub fn open_ports(&self) -> Vec<u16> {
+        self.ports
+            .iter()
+            .filter(|(_, state)| **state == PortState::Open)
+            .map(|(port, _)| *port)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn address_lists_keep_the_most_recent_entries() {
+        let mut device = NetworkDevice::new(
+            DeviceId::Mac("00:11:22:33:44:55".to_string()),
+            "00:11:22:33:44:55".to_string(),
+            String::new(),
+        );
+        let start = Utc::now();
+        device.record_address("192.168.1.20".parse().unwrap(), start);
+        // A new privacy address every day
+        for day in 1..=40 {
+            let address = format!("2001:db8::{:x}", day).parse().unwrap();
+            device.record_address(address, start + Duration::days(day));
+        }
+        // The IPv4 address is still in use
+        device.record_address("192.168.1.20".parse().unwrap(), start + Duration::days(41));
+
+        assert_eq!(device.ip_history.len(), MAX_IP_HISTORY);
+        assert!(device
+            .ip_history
+            .iter()
+            .any(|r| r.address == "192.168.1.20"));
+        assert!(!device.ip_history.iter().any(|r| r.address == "2001:db8::1"));
+        assert_eq!(device.ipv6_addresses.len(), MAX_IPV6_ADDRESSES);
+        assert_eq!(device.ipv6_addresses.first().unwrap(), "2001:db8::21");
+        assert_eq!(device.ipv6_addresses.last().unwrap(), "2001:db8::28");
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use pnet::datalink::MacAddr;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+pub const LOCALLY_ADMINISTERED: &str = "Locally administered";
+
+// Entries loaded from an oui.csv on disk. Checked before the database bundled at
+// build time, so a newer IEEE export can be dropped in without rebuilding.
+static OVERRIDES: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+// Randomized MACs (phones, laptops with privacy addresses) set the U/L bit
+pub fn is_locally_administered(mac: MacAddr) -> bool {
+    mac.0 & 0x02 != 0
+}
+
+pub fn lookup_vendor(mac: MacAddr) -> String {
+    if is_locally_administered(mac) {
+        return LOCALLY_ADMINISTERED.to_string();
+    }
+
+    let hex = format!(
+        "{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        mac.0, mac.1, mac.2, mac.3, mac.4, mac.5
+    );
+
+    // MA-S (36 bit), MA-M (28 bit), then MA-L (24 bit)
+    let overrides = OVERRIDES.read().unwrap();
+    for len in [9, 7, 6] {
+        if let Some(vendor) = overrides.get(&hex[..len]) {
+            return vendor.clone();
+        }
+    }
+
+    oui_data::lookup(&hex)
+        .map(|record| record.organization().to_string())
+        .unwrap_or_default()
+}
+
+// Loads an IEEE registry export (Registry,Assignment,Organization Name,...). The
+// MA-L, MA-M and MA-S files share the same layout and can all be loaded.
+pub fn load_csv(path: &Path) -> Result<usize> {
+    let mut reader = csv::Reader::from_path(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+    let mut entries = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let (Some(assignment), Some(organization)) = (record.get(1), record.get(2)) else {
+            continue;
+        };
+        let assignment = assignment.trim().to_uppercase();
+        if matches!(assignment.len(), 6 | 7 | 9) {
+            entries.insert(assignment, organization.trim().to_string());
+        }
+    }
+
+    let count = entries.len();
+    OVERRIDES.write().unwrap().extend(entries);
+    Ok(count)
+}
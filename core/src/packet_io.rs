@@ -0,0 +1,175 @@
+use anyhow::Result;
+use pnet::datalink::{self, Channel, NetworkInterface};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+// Longest a receiver blocks before giving its reader a chance to stop
+pub const READ_TIMEOUT: Duration = Duration::from_millis(250);
+
+// Sending half of a packet channel
+pub trait FrameSender: Send {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()>;
+}
+
+// Receiving half of a packet channel. A closed source reports `UnexpectedEof`,
+// an idle one `TimedOut` after at most READ_TIMEOUT.
+pub trait FrameReceiver: Send {
+    fn next_frame(&mut self) -> io::Result<&[u8]>;
+}
+
+// Opens packet channels on an interface. The scanner and killer are handed one of
+// these at construction so they can run against a real NIC or canned traffic.
+pub trait PacketIo: Send + Sync {
+    fn open(
+        &self,
+        interface: &NetworkInterface,
+    ) -> Result<(Box<dyn FrameSender>, Box<dyn FrameReceiver>)>;
+}
+
+// Live backend on top of pnet's datalink channels
+pub struct LivePacketIo;
+
+impl PacketIo for LivePacketIo {
+    fn open(
+        &self,
+        interface: &NetworkInterface,
+    ) -> Result<(Box<dyn FrameSender>, Box<dyn FrameReceiver>)> {
+        let config = datalink::Config {
+            read_timeout: Some(READ_TIMEOUT),
+            ..Default::default()
+        };
+        match datalink::channel(interface, config) {
+            Ok(Channel::Ethernet(tx, rx)) => {
+                Ok((Box::new(LiveSender(tx)), Box::new(LiveReceiver(rx))))
+            }
+            Ok(_) => Err(anyhow::anyhow!("Unsupported channel type")),
+            Err(e) => Err(anyhow::anyhow!("Failed to create channel: {}", e)),
+        }
+    }
+}
+
+struct LiveSender(Box<dyn datalink::DataLinkSender>);
+
+impl FrameSender for LiveSender {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        match self.0.send_to(frame, None) {
+            Some(Ok(_)) => Ok(()),
+            Some(Err(e)) => Err(e.into()),
+            None => Err(anyhow::anyhow!(
+                "Failed to send packet: the network interface may not be available"
+            )),
+        }
+    }
+}
+
+struct LiveReceiver(Box<dyn datalink::DataLinkReceiver>);
+
+impl FrameReceiver for LiveReceiver {
+    fn next_frame(&mut self) -> io::Result<&[u8]> {
+        self.0.next()
+    }
+}
+
+// In-memory backend. Frames passed to `inject` are handed to receivers in order,
+// and everything written by senders is kept for inspection.
+#[derive(Clone, Default)]
+pub struct MemoryPacketIo {
+    state: Arc<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    inbound: Mutex<Inbound>,
+    ready: Condvar,
+    sent: Mutex<Vec<Vec<u8>>>,
+}
+
+#[derive(Default)]
+struct Inbound {
+    frames: VecDeque<Vec<u8>>,
+    closed: bool,
+}
+
+impl MemoryPacketIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inject(&self, frame: impl Into<Vec<u8>>) {
+        let mut inbound = self.state.inbound.lock().unwrap();
+        inbound.frames.push_back(frame.into());
+        self.state.ready.notify_all();
+    }
+
+    // Receivers drain the remaining frames and then report end of stream
+    pub fn close(&self) {
+        self.state.inbound.lock().unwrap().closed = true;
+        self.state.ready.notify_all();
+    }
+
+    pub fn sent_frames(&self) -> Vec<Vec<u8>> {
+        self.state.sent.lock().unwrap().clone()
+    }
+}
+
+impl PacketIo for MemoryPacketIo {
+    fn open(
+        &self,
+        _interface: &NetworkInterface,
+    ) -> Result<(Box<dyn FrameSender>, Box<dyn FrameReceiver>)> {
+        Ok((
+            Box::new(MemorySender {
+                state: self.state.clone(),
+            }),
+            Box::new(MemoryReceiver {
+                state: self.state.clone(),
+                current: Vec::new(),
+            }),
+        ))
+    }
+}
+
+struct MemorySender {
+    state: Arc<MemoryState>,
+}
+
+impl FrameSender for MemorySender {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.state.sent.lock().unwrap().push(frame.to_vec());
+        Ok(())
+    }
+}
+
+struct MemoryReceiver {
+    state: Arc<MemoryState>,
+    current: Vec<u8>,
+}
+
+impl FrameReceiver for MemoryReceiver {
+    fn next_frame(&mut self) -> io::Result<&[u8]> {
+        let mut inbound = self.state.inbound.lock().unwrap();
+        loop {
+            if let Some(frame) = inbound.frames.pop_front() {
+                self.current = frame;
+                return Ok(&self.current);
+            }
+            if inbound.closed {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "packet source closed",
+                ));
+            }
+            let (next, wait) = self
+                .state
+                .ready
+                .wait_timeout(inbound, READ_TIMEOUT)
+                .unwrap();
+            if wait.timed_out() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no frame yet"));
+            }
+            inbound = next;
+        }
+    }
+}
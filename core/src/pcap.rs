@@ -0,0 +1,425 @@
+use crate::packet_io::{FrameReceiver, FrameSender, PacketIo};
+use anyhow::Result;
+use pcap_file::pcap::{PcapPacket, PcapReader, PcapWriter};
+use pcap_file::pcapng::{Block, PcapNgReader};
+use pcap_file::DataLink;
+use pnet::datalink::NetworkInterface;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const PCAPNG_MAGIC: [u8; 4] = [0x0A, 0x0D, 0x0D, 0x0A];
+// Sizes of the pcap file header and of the record header in front of each frame
+const GLOBAL_HEADER_LEN: u64 = 24;
+const PACKET_HEADER_LEN: u64 = 16;
+
+enum CaptureFormat {
+    Pcap(PcapReader<BufReader<File>>),
+    PcapNg(PcapNgReader<BufReader<File>>),
+}
+
+// Reads Ethernet frames out of a .pcap or .pcapng file so they can be fed through
+// the scanner's receive path in place of a live interface
+pub struct CaptureReader {
+    format: CaptureFormat,
+    current: Vec<u8>,
+    finished: bool,
+}
+
+impl CaptureReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let reader = BufReader::new(file);
+        let format = if magic == PCAPNG_MAGIC {
+            CaptureFormat::PcapNg(PcapNgReader::new(reader)?)
+        } else {
+            let reader = PcapReader::new(reader)?;
+            let datalink = reader.header().datalink;
+            if datalink != DataLink::ETHERNET {
+                return Err(anyhow::anyhow!(
+                    "Unsupported capture link type {:?}, only Ethernet captures can be replayed",
+                    datalink
+                ));
+            }
+            CaptureFormat::Pcap(reader)
+        };
+
+        Ok(Self {
+            format,
+            current: Vec::new(),
+            finished: false,
+        })
+    }
+
+    fn read_next(&mut self) -> Option<Result<()>> {
+        match &mut self.format {
+            CaptureFormat::Pcap(reader) => {
+                let packet = match reader.next_packet()? {
+                    Ok(packet) => packet,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                self.current = packet.data.into_owned();
+                Some(Ok(()))
+            }
+            CaptureFormat::PcapNg(reader) => loop {
+                let block = match reader.next_block()? {
+                    Ok(block) => block,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                let (interface_id, data) = match block {
+                    Block::EnhancedPacket(packet) => (packet.interface_id, packet.data.into_owned()),
+                    Block::SimplePacket(packet) => (0, packet.data.into_owned()),
+                    Block::Packet(packet) => (packet.interface_id as u32, packet.data.into_owned()),
+                    _ => continue,
+                };
+                // pcapng files may mix link types per interface; skip anything that isn't Ethernet
+                let is_ethernet = reader
+                    .interfaces()
+                    .get(interface_id as usize)
+                    .map(|iface| iface.linktype == DataLink::ETHERNET)
+                    .unwrap_or(false);
+                if is_ethernet {
+                    self.current = data;
+                    return Some(Ok(()));
+                }
+            },
+        }
+    }
+}
+
+impl FrameReceiver for CaptureReader {
+    fn next_frame(&mut self) -> io::Result<&[u8]> {
+        if !self.finished {
+            match self.read_next() {
+                Some(Ok(())) => return Ok(&self.current),
+                Some(Err(e)) => {
+                    // A truncated or corrupt capture ends the replay
+                    self.finished = true;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+                }
+                None => self.finished = true,
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "end of capture file",
+        ))
+    }
+}
+
+// Where and how much scanner traffic to record
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    pub path: PathBuf,
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+}
+
+impl CaptureConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+
+    // capture.pcap -> capture.1.pcap, capture.2.pcap, ...
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "capture".to_string());
+        let extension = self
+            .path
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "pcap".to_string());
+        self.path
+            .with_file_name(format!("{}.{}.{}", stem, index, extension))
+    }
+}
+
+// Writes frames to a pcap file and rolls over to a new one once the size limit is
+// reached, keeping at most `max_files` old files around
+pub struct CaptureWriter {
+    config: CaptureConfig,
+    writer: Option<PcapWriter<File>>,
+    // Bytes in the current file, headers included
+    written: u64,
+}
+
+impl CaptureWriter {
+    pub fn create(config: CaptureConfig) -> Result<Self> {
+        let writer = Some(Self::open_writer(&config.path)?);
+        Ok(Self {
+            config,
+            writer,
+            written: GLOBAL_HEADER_LEN,
+        })
+    }
+
+    // Unbuffered so the file is usable as evidence while the scanner is still running
+    fn open_writer(path: &Path) -> Result<PcapWriter<File>> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+        Ok(PcapWriter::new(file)?)
+    }
+
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        let size = PACKET_HEADER_LEN + frame.len() as u64;
+        // No writer means the last rotation failed part way, so try it again
+        if self.writer.is_none()
+            || (self.written > GLOBAL_HEADER_LEN
+                && self.written + size > self.config.max_file_bytes)
+        {
+            self.rotate()?;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let packet = PcapPacket::new(timestamp, frame.len() as u32, frame);
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Err(anyhow::anyhow!("Capture file is closed")),
+        };
+        self.written += writer.write_packet(&packet)? as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        // Close the current file first, Windows refuses to rename open files
+        self.writer = None;
+        if self.config.path.exists() {
+            if self.config.max_files == 0 {
+                fs::remove_file(&self.config.path)?;
+            } else {
+                let _ = fs::remove_file(self.config.rotated_path(self.config.max_files));
+                for index in (1..self.config.max_files).rev() {
+                    let from = self.config.rotated_path(index);
+                    if from.exists() {
+                        fs::rename(&from, self.config.rotated_path(index + 1))?;
+                    }
+                }
+                fs::rename(&self.config.path, self.config.rotated_path(1))?;
+            }
+        }
+        self.writer = Some(Self::open_writer(&self.config.path)?);
+        self.written = GLOBAL_HEADER_LEN;
+        Ok(())
+    }
+}
+
+// Wraps another backend and records every frame sent or received through it
+pub struct RecordingPacketIo {
+    inner: Arc<dyn PacketIo>,
+    writer: Arc<Mutex<CaptureWriter>>,
+}
+
+impl RecordingPacketIo {
+    pub fn new(inner: Arc<dyn PacketIo>, config: CaptureConfig) -> Result<Self> {
+        Ok(Self {
+            inner,
+            writer: Arc::new(Mutex::new(CaptureWriter::create(config)?)),
+        })
+    }
+}
+
+impl PacketIo for RecordingPacketIo {
+    fn open(
+        &self,
+        interface: &NetworkInterface,
+    ) -> Result<(Box<dyn FrameSender>, Box<dyn FrameReceiver>)> {
+        let (tx, rx) = self.inner.open(interface)?;
+        Ok((
+            Box::new(RecordingSender {
+                inner: tx,
+                writer: self.writer.clone(),
+            }),
+            Box::new(RecordingReceiver {
+                inner: rx,
+                writer: self.writer.clone(),
+            }),
+        ))
+    }
+}
+
+fn record(writer: &Mutex<CaptureWriter>, frame: &[u8]) {
+    if let Err(e) = writer.lock().unwrap().write_frame(frame) {
+        warn!(error = %e, "Failed to write capture");
+    }
+}
+
+struct RecordingSender {
+    inner: Box<dyn FrameSender>,
+    writer: Arc<Mutex<CaptureWriter>>,
+}
+
+impl FrameSender for RecordingSender {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.inner.send_frame(frame)?;
+        record(&self.writer, frame);
+        Ok(())
+    }
+}
+
+struct RecordingReceiver {
+    inner: Box<dyn FrameReceiver>,
+    writer: Arc<Mutex<CaptureWriter>>,
+}
+
+impl FrameReceiver for RecordingReceiver {
+    fn next_frame(&mut self) -> io::Result<&[u8]> {
+        let frame = self.inner.next_frame()?;
+        record(&self.writer, frame);
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admission::AdmissionPolicy;
+    use crate::events::EventBus;
+    use crate::models::{DeviceId, DeviceTable};
+    use crate::scanner::NetworkScanner;
+    use dashmap::DashMap;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    async fn replay(name: &str) -> Arc<DeviceTable> {
+        let devices = Arc::new(DashMap::new());
+        NetworkScanner::replay_capture(
+            &fixture(name),
+            AdmissionPolicy::new(Vec::new()),
+            devices.clone(),
+            EventBus::new(),
+        )
+        .await
+        .unwrap();
+        devices
+    }
+
+    fn ip_of(devices: &DeviceTable, mac: &str) -> Option<String> {
+        devices
+            .get(&DeviceId::Mac(mac.to_string()))
+            .map(|device| device.ip_address.clone())
+    }
+
+    // Two ARP replies
+    #[tokio::test]
+    async fn replays_pcap() {
+        let devices = replay("arp.pcap").await;
+        assert_eq!(devices.len(), 2);
+        assert_eq!(
+            ip_of(&devices, "00:11:22:33:44:55").as_deref(),
+            Some("192.168.50.3")
+        );
+        assert_eq!(
+            ip_of(&devices, "00:11:22:33:44:66").as_deref(),
+            Some("192.168.50.4")
+        );
+    }
+
+    // An ARP reply and an ICMP echo reply on an Ethernet interface, with a raw IP
+    // packet from a second interface in between
+    #[tokio::test]
+    async fn replays_pcapng_skipping_other_link_types() {
+        let devices = replay("mixed.pcapng").await;
+        assert_eq!(devices.len(), 2);
+        assert_eq!(
+            ip_of(&devices, "00:11:22:33:44:55").as_deref(),
+            Some("192.168.50.3")
+        );
+        assert_eq!(
+            ip_of(&devices, "00:11:22:33:44:77").as_deref(),
+            Some("192.168.50.5")
+        );
+    }
+
+    // Linux cooked capture, as written by `tcpdump -i any`
+    #[test]
+    fn rejects_non_ethernet_pcap() {
+        let error = CaptureReader::open(&fixture("cooked.pcap")).err().unwrap();
+        assert!(error.to_string().contains("only Ethernet captures"));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ndm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn frames_in(path: &Path) -> usize {
+        let mut reader = CaptureReader::open(path).unwrap();
+        let mut count = 0;
+        while reader.next_frame().is_ok() {
+            count += 1;
+        }
+        count
+    }
+
+    // Two 60 byte frames fit under the limit along with the headers, a third doesn't
+    #[test]
+    fn rotates_before_files_outgrow_the_limit() {
+        let dir = temp_dir("rotate");
+        let mut config = CaptureConfig::new(dir.join("capture.pcap"));
+        config.max_file_bytes = 200;
+        config.max_files = 2;
+        let mut writer = CaptureWriter::create(config.clone()).unwrap();
+        for _ in 0..7 {
+            writer.write_frame(&[0u8; 60]).unwrap();
+        }
+        drop(writer);
+
+        for path in [
+            config.path.clone(),
+            config.rotated_path(1),
+            config.rotated_path(2),
+        ] {
+            assert!(fs::metadata(&path).unwrap().len() <= config.max_file_bytes);
+            assert!(frames_in(&path) > 0);
+        }
+        assert_eq!(frames_in(&config.path), 1);
+        assert!(!config.rotated_path(3).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recovers_from_a_failed_rotation() {
+        let dir = temp_dir("rotate-fail");
+        let mut config = CaptureConfig::new(dir.join("capture.pcap"));
+        config.max_file_bytes = 200;
+        config.max_files = 1;
+        // A directory in the way of the rotated file makes the rename fail
+        let blocker = config.rotated_path(1);
+        fs::create_dir_all(blocker.join("in-the-way")).unwrap();
+
+        let mut writer = CaptureWriter::create(config.clone()).unwrap();
+        writer.write_frame(&[0u8; 60]).unwrap();
+        writer.write_frame(&[0u8; 60]).unwrap();
+        assert!(writer.write_frame(&[0u8; 60]).is_err());
+        assert!(writer.write_frame(&[0u8; 60]).is_err());
+
+        fs::remove_dir_all(&blocker).unwrap();
+        writer.write_frame(&[0u8; 60]).unwrap();
+        drop(writer);
+        assert_eq!(frames_in(&config.rotated_path(1)), 2);
+        assert_eq!(frames_in(&config.path), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+// Ports the full sweep has always probed
+pub const DEFAULT_TCP_PORTS: [u16; 5] = [22, 80, 443, 3389, 8080];
+
+// Which probes a scan sends to every address in the subnet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeProfile {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub arp: bool,
+    #[serde(default)]
+    pub icmp: bool,
+    #[serde(default)]
+    pub tcp_ports: Vec<u16>,
+    // IPv6 all-nodes echo and neighbor solicitations
+    #[serde(default = "default_true")]
+    pub ndp: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ProbeProfile {
+    pub fn arp_only() -> Self {
+        Self {
+            name: "ARP only".to_string(),
+            arp: true,
+            icmp: false,
+            tcp_ports: Vec::new(),
+            ndp: true,
+        }
+    }
+
+    pub fn arp_icmp() -> Self {
+        Self {
+            name: "ARP + ICMP".to_string(),
+            arp: true,
+            icmp: true,
+            tcp_ports: Vec::new(),
+            ndp: true,
+        }
+    }
+
+    pub fn full_sweep() -> Self {
+        Self {
+            name: "Full service sweep".to_string(),
+            arp: true,
+            icmp: true,
+            tcp_ports: DEFAULT_TCP_PORTS.to_vec(),
+            ndp: true,
+        }
+    }
+
+    pub fn builtin() -> Vec<Self> {
+        vec![Self::arp_only(), Self::arp_icmp(), Self::full_sweep()]
+    }
+}
+
+impl Default for ProbeProfile {
+    fn default() -> Self {
+        Self::full_sweep()
+    }
+}
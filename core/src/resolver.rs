@@ -0,0 +1,489 @@
+use crate::dns::{self, Message, RecordData};
+use crate::events::{Event, EventBus};
+use crate::identity;
+use crate::models::DeviceTable;
+use dashmap::{DashMap, DashSet};
+use rand::random;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::Instrument;
+
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+// Asks an mDNS responder to answer straight back to our port
+const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+const NB_FLAG_GROUP: u16 = 0x8000;
+
+// Where active lookups are sent. Every address is overridable so the resolver can
+// be pointed at a local stub responder.
+#[derive(Debug, Clone)]
+pub struct ResolverConfig {
+    // PTR queries go here; defaults to the system resolver, then the gateway
+    pub dns_server: Option<SocketAddr>,
+    pub nbns_port: u16,
+    pub mdns_port: u16,
+    pub timeout: Duration,
+    pub cache_ttl: Duration,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            dns_server: None,
+            nbns_port: 137,
+            mdns_port: 5353,
+            timeout: Duration::from_millis(1500),
+            cache_ttl: Duration::from_secs(600),
+        }
+    }
+}
+
+struct CacheEntry {
+    name: Option<String>,
+    expires: Instant,
+}
+
+// Looks up names for discovered hosts with reverse DNS, unicast mDNS and NetBIOS
+// node status queries. Answers, including misses, are cached until they expire.
+pub struct HostnameResolver {
+    config: ResolverConfig,
+    dns_server: Option<SocketAddr>,
+    cache: DashMap<IpAddr, CacheEntry>,
+}
+
+impl HostnameResolver {
+    pub fn new(config: ResolverConfig) -> Self {
+        let dns_server = config.dns_server.or_else(system_dns_server);
+        Self {
+            config,
+            dns_server,
+            cache: DashMap::new(),
+        }
+    }
+
+    // Resolves addresses as they are queued and writes the names into the device
+    // table, until the queue closes or `shutdown` is cancelled. Lookups run on
+    // `tasks` so whoever waits on it knows none are still writing to the table.
+    pub async fn run(
+        self: Arc<Self>,
+        devices: Arc<DeviceTable>,
+        mut requests: mpsc::UnboundedReceiver<IpAddr>,
+        events: EventBus,
+        tasks: TaskTracker,
+        shutdown: CancellationToken,
+    ) {
+        // Every rescan queues each device again, one lookup per address is enough
+        let in_flight = Arc::new(DashSet::new());
+        loop {
+            let ip = tokio::select! {
+                ip = requests.recv() => ip,
+                _ = shutdown.cancelled() => None,
+            };
+            let Some(ip) = ip else {
+                break;
+            };
+            if !in_flight.insert(ip) {
+                continue;
+            }
+            let resolver = self.clone();
+            let devices = devices.clone();
+            let events = events.clone();
+            let in_flight = in_flight.clone();
+            let shutdown = shutdown.clone();
+            tasks.spawn(
+                async move {
+                    tokio::select! {
+                        _ = resolver.update_hostname(&devices, &events, ip) => {}
+                        _ = shutdown.cancelled() => {}
+                    }
+                    in_flight.remove(&ip);
+                }
+                .in_current_span(),
+            );
+        }
+    }
+
+    async fn update_hostname(&self, devices: &DeviceTable, events: &EventBus, ip: IpAddr) {
+        let previous = self.cache.get(&ip).and_then(|entry| entry.name.clone());
+        let Some(name) = self.resolve(ip).await else {
+            return;
+        };
+        let id = identity::find_by_ip(devices, ip);
+        let updated = id.and_then(|id| {
+            let mut device = devices.get_mut(&id)?;
+            // Don't clobber a name learned some other way, only fill gaps or
+            // refresh what we resolved last time
+            let replaceable =
+                device.hostname.is_empty() || Some(&device.hostname) == previous.as_ref();
+            if !replaceable {
+                return None;
+            }
+            let renamed = device.hostname != name;
+            device.set_hostname(name);
+            renamed.then(|| Box::new(device.clone()))
+        });
+        if let Some(device) = updated {
+            events.publish(Event::DeviceUpdated { device }).await;
+        }
+    }
+
+    fn cached(&self, ip: IpAddr) -> Option<Option<String>> {
+        let entry = self.cache.get(&ip)?;
+        if entry.expires > Instant::now() {
+            Some(entry.name.clone())
+        } else {
+            None
+        }
+    }
+
+    pub async fn resolve(&self, ip: IpAddr) -> Option<String> {
+        if let Some(name) = self.cached(ip) {
+            return name;
+        }
+        let IpAddr::V4(ipv4) = ip else {
+            return None;
+        };
+
+        let mut ttl = self.config.cache_ttl;
+        let mut name = None;
+        if let Some((ptr, record_ttl)) = self.reverse_dns(ipv4).await {
+            ttl = ttl.min(Duration::from_secs(record_ttl.max(30) as u64));
+            name = Some(ptr);
+        }
+        if name.is_none() {
+            name = self.mdns_query(ipv4).await;
+        }
+        if name.is_none() {
+            name = self.nbns_node_status(ipv4).await;
+        }
+
+        self.cache.insert(
+            ip,
+            CacheEntry {
+                name: name.clone(),
+                expires: Instant::now() + ttl,
+            },
+        );
+        name
+    }
+
+    async fn reverse_dns(&self, ip: Ipv4Addr) -> Option<(String, u32)> {
+        let server = self.dns_server?;
+        let query_id = random::<u16>();
+        let query = dns::build_query(
+            query_id,
+            FLAG_RECURSION_DESIRED,
+            &dns::reverse_name(ip),
+            dns::TYPE_PTR,
+            dns::CLASS_IN,
+        );
+        let response = self.exchange(server, &query, query_id).await?;
+        response
+            .records
+            .into_iter()
+            .find_map(|record| match record.data {
+                RecordData::Ptr(target) if !target.is_empty() => {
+                    Some((target.trim_end_matches('.').to_string(), record.ttl))
+                }
+                _ => None,
+            })
+    }
+
+    async fn mdns_query(&self, ip: Ipv4Addr) -> Option<String> {
+        let query_id = random::<u16>();
+        let query = dns::build_query(
+            query_id,
+            0,
+            &dns::reverse_name(ip),
+            dns::TYPE_PTR,
+            dns::CLASS_IN | CLASS_UNICAST_RESPONSE,
+        );
+        let target = SocketAddr::new(IpAddr::V4(ip), self.config.mdns_port);
+        let response = self.exchange(target, &query, query_id).await?;
+        response
+            .records
+            .into_iter()
+            .find_map(|record| match record.data {
+                RecordData::Ptr(target) => {
+                    let name = target.trim_end_matches('.');
+                    let name = name.strip_suffix(".local").unwrap_or(name);
+                    (!name.is_empty()).then(|| name.to_string())
+                }
+                _ => None,
+            })
+    }
+
+    async fn nbns_node_status(&self, ip: Ipv4Addr) -> Option<String> {
+        let query_id = random::<u16>();
+        let query = dns::build_query(
+            query_id,
+            0,
+            &dns::encode_netbios_name("*", 0x00),
+            dns::TYPE_NBSTAT,
+            dns::CLASS_IN,
+        );
+        let target = SocketAddr::new(IpAddr::V4(ip), self.config.nbns_port);
+        let response = self.exchange(target, &query, query_id).await?;
+        let record = response
+            .records
+            .into_iter()
+            .find(|record| record.rtype == dns::TYPE_NBSTAT)?;
+        let RecordData::Other(data) = record.data else {
+            return None;
+        };
+
+        // Node status: a count followed by 18-byte entries of name(15), suffix(1), flags(2).
+        // The unique workstation name is the machine's own name.
+        let count = *data.first()? as usize;
+        data.get(1..1 + count * 18)?
+            .chunks(18)
+            .find(|entry| {
+                let flags = u16::from_be_bytes([entry[16], entry[17]]);
+                entry[15] == 0x00 && flags & NB_FLAG_GROUP == 0
+            })
+            .map(|entry| String::from_utf8_lossy(&entry[..15]).trim_end().to_string())
+            .filter(|name| !name.is_empty())
+    }
+
+    async fn exchange(&self, target: SocketAddr, query: &[u8], query_id: u16) -> Option<Message> {
+        let bind_addr: SocketAddr = match target {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(bind_addr).await.ok()?;
+        socket.send_to(query, target).await.ok()?;
+
+        let mut buf = [0u8; 1500];
+        let deadline = Instant::now() + self.config.timeout;
+        loop {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            let (len, from) = time::timeout(remaining, socket.recv_from(&mut buf))
+                .await
+                .ok()?
+                .ok()?;
+            if from.ip() != target.ip() {
+                continue;
+            }
+            if let Some(message) = Message::parse(&buf[..len]) {
+                if message.is_response() && message.id == query_id {
+                    return Some(message);
+                }
+            }
+        }
+    }
+}
+
+// First nameserver from resolv.conf, falling back to the default gateway which
+// usually runs a forwarder that knows the DHCP clients
+fn system_dns_server() -> Option<SocketAddr> {
+    #[cfg(unix)]
+    {
+        if let Ok(text) = std::fs::read_to_string("/etc/resolv.conf") {
+            let server = text.lines().find_map(|line| {
+                let mut parts = line.split_whitespace();
+                match (parts.next(), parts.next()) {
+                    (Some("nameserver"), Some(addr)) => addr.parse::<IpAddr>().ok(),
+                    _ => None,
+                }
+            });
+            if let Some(server) = server {
+                return Some(SocketAddr::new(server, 53));
+            }
+        }
+    }
+    default_net::get_default_gateway()
+        .ok()
+        .map(|gateway| SocketAddr::new(gateway.ip_addr, 53))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const HOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    // Answers every query on a loopback port with `answer`, or with an empty
+    // response when there is none, and counts the queries it got
+    struct Stub {
+        port: u16,
+        queries: Arc<AtomicUsize>,
+    }
+
+    impl Stub {
+        async fn start(answer: Option<(u16, Vec<u8>)>) -> Self {
+            let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let port = socket.local_addr().unwrap().port();
+            let queries = Arc::new(AtomicUsize::new(0));
+            let counter = queries.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1500];
+                while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let response = respond(&buf[..len], answer.as_ref());
+                    let _ = socket.send_to(&response, from).await;
+                }
+            });
+            Self { port, queries }
+        }
+
+        // Counts queries and never answers them
+        async fn silent() -> Self {
+            let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let port = socket.local_addr().unwrap().port();
+            let queries = Arc::new(AtomicUsize::new(0));
+            let counter = queries.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1500];
+                while socket.recv_from(&mut buf).await.is_ok() {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            Self { port, queries }
+        }
+
+        fn queries(&self) -> usize {
+            self.queries.load(Ordering::SeqCst)
+        }
+    }
+
+    // The query turned into a response, with one answer for the question's name
+    fn respond(query: &[u8], answer: Option<&(u16, Vec<u8>)>) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2..4].copy_from_slice(&0x8400u16.to_be_bytes());
+        if let Some((rtype, rdata)) = answer {
+            response[6..8].copy_from_slice(&1u16.to_be_bytes());
+            // Pointer to the question name
+            response.extend_from_slice(&[0xC0, 0x0C]);
+            response.extend_from_slice(&rtype.to_be_bytes());
+            response.extend_from_slice(&dns::CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&3600u32.to_be_bytes());
+            response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            response.extend_from_slice(rdata);
+        }
+        response
+    }
+
+    fn ptr(name: &str) -> Option<(u16, Vec<u8>)> {
+        let mut rdata = Vec::new();
+        for label in name.split('.') {
+            rdata.push(label.len() as u8);
+            rdata.extend_from_slice(label.as_bytes());
+        }
+        rdata.push(0);
+        Some((dns::TYPE_PTR, rdata))
+    }
+
+    fn node_status(entries: &[(&str, u16)]) -> Option<(u16, Vec<u8>)> {
+        let mut rdata = vec![entries.len() as u8];
+        for (name, flags) in entries {
+            rdata.extend_from_slice(format!("{:<15}", name).as_bytes());
+            rdata.push(0x00);
+            rdata.extend_from_slice(&flags.to_be_bytes());
+        }
+        Some((dns::TYPE_NBSTAT, rdata))
+    }
+
+    fn resolver(dns: &Stub, mdns: &Stub, nbns: &Stub) -> HostnameResolver {
+        HostnameResolver::new(ResolverConfig {
+            dns_server: Some(SocketAddr::new(HOST, dns.port)),
+            nbns_port: nbns.port,
+            mdns_port: mdns.port,
+            timeout: Duration::from_secs(2),
+            cache_ttl: Duration::from_millis(200),
+        })
+    }
+
+    #[tokio::test]
+    async fn uses_reverse_dns_first() {
+        let dns = Stub::start(ptr("printer.lan")).await;
+        let mdns = Stub::start(ptr("printer.local")).await;
+        let nbns = Stub::start(None).await;
+        let resolver = resolver(&dns, &mdns, &nbns);
+
+        assert_eq!(resolver.resolve(HOST).await.as_deref(), Some("printer.lan"));
+        assert_eq!(resolver.resolve(HOST).await.as_deref(), Some("printer.lan"));
+        assert_eq!(dns.queries(), 1);
+        assert_eq!(mdns.queries(), 0);
+        assert_eq!(nbns.queries(), 0);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_mdns() {
+        let dns = Stub::start(None).await;
+        let mdns = Stub::start(ptr("nas.local")).await;
+        let nbns = Stub::start(None).await;
+        let resolver = resolver(&dns, &mdns, &nbns);
+
+        assert_eq!(resolver.resolve(HOST).await.as_deref(), Some("nas"));
+        assert_eq!(dns.queries(), 1);
+        assert_eq!(nbns.queries(), 0);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_nbns_workstation_name() {
+        let dns = Stub::start(None).await;
+        let mdns = Stub::start(None).await;
+        let nbns = Stub::start(node_status(&[
+            ("WORKGROUP", NB_FLAG_GROUP),
+            ("DESKTOP-1", 0x0400),
+        ]))
+        .await;
+        let resolver = resolver(&dns, &mdns, &nbns);
+
+        assert_eq!(resolver.resolve(HOST).await.as_deref(), Some("DESKTOP-1"));
+        assert_eq!(mdns.queries(), 1);
+    }
+
+    #[tokio::test]
+    async fn caches_answers_and_misses_until_they_expire() {
+        let dns = Stub::start(None).await;
+        let mdns = Stub::start(None).await;
+        let nbns = Stub::start(None).await;
+        let resolver = resolver(&dns, &mdns, &nbns);
+
+        assert_eq!(resolver.resolve(HOST).await, None);
+        assert_eq!(resolver.resolve(HOST).await, None);
+        assert_eq!(dns.queries(), 1);
+        assert_eq!(nbns.queries(), 1);
+
+        time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(resolver.resolve(HOST).await, None);
+        assert_eq!(dns.queries(), 2);
+    }
+
+    #[tokio::test]
+    async fn runs_one_lookup_per_address_until_shut_down() {
+        let dns = Stub::silent().await;
+        let mdns = Stub::silent().await;
+        let nbns = Stub::silent().await;
+        let resolver = Arc::new(resolver(&dns, &mdns, &nbns));
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let tasks = TaskTracker::new();
+        let shutdown = CancellationToken::new();
+        tasks.spawn(resolver.run(
+            Arc::new(DashMap::new()),
+            receiver,
+            EventBus::new(),
+            tasks.clone(),
+            shutdown.clone(),
+        ));
+
+        // A rescan queues the address again while the first lookup is still waiting
+        requests.send(HOST).unwrap();
+        requests.send(HOST).unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(dns.queries(), 1);
+
+        shutdown.cancel();
+        tasks.close();
+        time::timeout(Duration::from_millis(500), tasks.wait())
+            .await
+            .unwrap();
+    }
+}
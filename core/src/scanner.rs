@@ -0,0 +1,1277 @@
+use crate::admission::AdmissionPolicy;
+use crate::diff::{ScanDiff, Snapshot};
+use crate::events::{Event, EventBus};
+use crate::hostnames;
+use crate::identity;
+use crate::metrics::{CountingPacketIo, Metrics};
+use crate::models::{DeviceId, DeviceStatus, DeviceTable, NetworkDevice, PortState};
+use crate::oui;
+use crate::packet_io::{FrameReceiver, FrameSender, PacketIo};
+use crate::pcap::{CaptureConfig, CaptureReader, RecordingPacketIo};
+use crate::profile::ProbeProfile;
+use crate::resolver::{HostnameResolver, ResolverConfig};
+use anyhow::Result;
+use chrono::Utc;
+use dashmap::DashMap;
+use ipnetwork::IpNetwork;
+use pnet::datalink::{MacAddr, NetworkInterface};
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::icmp::{echo_request, IcmpTypes, MutableIcmpPacket};
+use pnet::packet::icmpv6::{self, Icmpv6Type, Icmpv6Types, MutableIcmpv6Packet};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::{self, Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
+use pnet::packet::tcp::{self, MutableTcpPacket, TcpFlags, TcpPacket};
+use pnet::packet::udp::UdpPacket;
+use pnet::packet::Packet;
+use rand::{random, random_range};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info, trace, warn, Instrument, Span};
+
+pub enum ScanCommand {
+    Scan(ProbeProfile),
+}
+
+// How long a SYN probe may go unanswered before the port counts as filtered
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+// Replies trickle in after the probes go out, so wait this long before comparing scans
+const SCAN_SETTLE: Duration = PROBE_TIMEOUT;
+
+// A SYN we sent and are waiting on, keyed by (target ip, target port)
+struct PendingProbe {
+    source_port: u16,
+    sent_at: Instant,
+}
+
+type PendingProbes = Arc<DashMap<(Ipv4Addr, u16), PendingProbe>>;
+
+// Shared state the receive path needs to turn frames into devices
+#[derive(Clone)]
+struct ReceiveContext {
+    devices: Arc<DeviceTable>,
+    pending_probes: PendingProbes,
+    events: EventBus,
+    router_mac: MacAddr,
+    router_ip: IpAddr,
+    admission: AdmissionPolicy,
+    // New hosts are queued here for active name resolution
+    resolve_sender: Option<mpsc::UnboundedSender<IpAddr>>,
+}
+
+pub struct NetworkScanner {
+    interface: NetworkInterface,
+    io: Arc<dyn PacketIo>,
+    devices: Arc<DeviceTable>,
+    events: EventBus,
+    command_receiver: mpsc::UnboundedReceiver<ScanCommand>,
+    router_mac: MacAddr,
+    capture: Option<CaptureConfig>,
+    admission: AdmissionPolicy,
+    pending_probes: PendingProbes,
+    initial_profile: ProbeProfile,
+    resolver_config: ResolverConfig,
+    metrics: Arc<Metrics>,
+    shutdown: CancellationToken,
+}
+
+impl NetworkScanner {
+    pub fn new(
+        interface: NetworkInterface,
+        io: Arc<dyn PacketIo>,
+        devices: Arc<DeviceTable>,
+        events: EventBus,
+        command_receiver: mpsc::UnboundedReceiver<ScanCommand>,
+    ) -> Self {
+        let router_mac = default_net::get_default_gateway()
+            .ok()
+            .map(|g| {
+                let bytes = g.mac_addr.octets();
+                MacAddr::new(bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5])
+            })
+            .unwrap_or_else(MacAddr::zero);
+        let admission = AdmissionPolicy::for_interface(&interface, &[]);
+        Self {
+            interface,
+            io,
+            devices,
+            events,
+            command_receiver,
+            router_mac,
+            capture: None,
+            admission,
+            pending_probes: Arc::new(DashMap::new()),
+            initial_profile: ProbeProfile::default(),
+            resolver_config: ResolverConfig::default(),
+            metrics: Arc::new(Metrics::new()),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    pub fn set_resolver_config(&mut self, config: ResolverConfig) {
+        self.resolver_config = config;
+    }
+
+    // Profile used for the sweep that runs as soon as the scanner starts
+    pub fn set_initial_profile(&mut self, profile: ProbeProfile) {
+        self.initial_profile = profile;
+    }
+
+    // Admit hosts from these ranges in addition to the interface's own subnets
+    pub fn set_extra_ranges(&mut self, ranges: &[IpNetwork]) {
+        self.admission = AdmissionPolicy::for_interface(&self.interface, ranges);
+    }
+
+    // Record every frame the scanner sends or receives to a rotating pcap file
+    pub fn set_capture(&mut self, capture: Option<CaptureConfig>) {
+        self.capture = capture;
+    }
+
+    // Count frames and scan times into these rather than a private set
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = metrics;
+    }
+
+    // Stop scanning once this is cancelled
+    pub fn set_shutdown(&mut self, shutdown: CancellationToken) {
+        self.shutdown = shutdown;
+    }
+
+    // Runs until shut down or until scanning fails, then waits for the listener,
+    // liveness and resolver tasks to finish
+    pub async fn start(&mut self) -> Result<()> {
+        info!("Starting scanner");
+        // Cancelled on failure too, without taking the rest of the session down
+        let stop = self.shutdown.child_token();
+        let tasks = TaskTracker::new();
+        let result = tokio::select! {
+            result = self.run(&tasks, &stop) => result,
+            _ = stop.cancelled() => Ok(()),
+        };
+        stop.cancel();
+        tasks.close();
+        tasks.wait().await;
+        info!("Scanner stopped");
+        result
+    }
+
+    async fn run(&mut self, tasks: &TaskTracker, stop: &CancellationToken) -> Result<()> {
+        let io: Arc<dyn PacketIo> = match &self.capture {
+            Some(config) => {
+                info!(path = %config.path.display(), "Recording traffic");
+                Arc::new(RecordingPacketIo::new(self.io.clone(), config.clone())?)
+            }
+            None => self.io.clone(),
+        };
+        let io = CountingPacketIo::new(io, self.metrics.clone());
+        let (mut tx, rx) = io.open(&self.interface)?;
+
+        // Active hostname resolution task
+        let (resolve_sender, resolve_receiver) = mpsc::unbounded_channel();
+        let resolver = Arc::new(HostnameResolver::new(self.resolver_config.clone()));
+        tasks.spawn(
+            resolver
+                .run(
+                    self.devices.clone(),
+                    resolve_receiver,
+                    self.events.clone(),
+                    tasks.clone(),
+                    stop.clone(),
+                )
+                .instrument(Span::current()),
+        );
+
+        let context = ReceiveContext {
+            devices: self.devices.clone(),
+            pending_probes: self.pending_probes.clone(),
+            events: self.events.clone(),
+            router_mac: self.router_mac,
+            router_ip: default_net::get_default_gateway()
+                .ok()
+                .map(|g| g.ip_addr)
+                .unwrap_or(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))),
+            admission: self.admission.clone(),
+            resolve_sender: Some(resolve_sender.clone()),
+        };
+
+        // ARP listener task. Reading frames blocks, so it gets its own thread rather
+        // than tying up a runtime worker.
+        let handle = tokio::runtime::Handle::current();
+        let span = Span::current();
+        let listener_stop = stop.clone();
+        tasks.spawn_blocking(move || {
+            let _span = span.enter();
+            handle.block_on(Self::receive_frames(rx, &context, &listener_stop));
+        });
+
+        // Background scanning task
+        let devices = self.devices.clone();
+        let pending_probes = self.pending_probes.clone();
+        let events = self.events.clone();
+        tasks.spawn(
+            Self::start_background_scan(devices, pending_probes, events, stop.clone())
+                .instrument(Span::current()),
+        );
+
+        // Initial probe
+        let initial_profile = self.initial_profile.clone();
+        let scan_started = Utc::now();
+        let timer = Instant::now();
+        self.publish_scan_started(&initial_profile).await;
+        self.probe_devices(&mut tx, &initial_profile).await?;
+        time::sleep(SCAN_SETTLE).await;
+        Self::expire_probes(&self.devices, &self.pending_probes);
+        // Baseline the first requested scan is compared against
+        let mut previous = Snapshot::capture(&self.devices, scan_started);
+        self.metrics
+            .record_scan(&initial_profile.name, timer.elapsed());
+        info!(profile = %initial_profile.name, elapsed = ?timer.elapsed(), "Scan finished");
+        self.events
+            .publish(Event::ScanFinished {
+                profile: initial_profile.name.clone(),
+                diff: None,
+            })
+            .await;
+
+        // Proxy ARP detection: the router answering for other hosts leaves several
+        // IPv4 addresses in its history
+        let mut mac_to_ips: std::collections::HashMap<MacAddr, Vec<Ipv4Addr>> =
+            std::collections::HashMap::new();
+        for entry in self.devices.iter() {
+            let device = entry.value();
+            if let Ok(mac) = device.mac_address.parse::<MacAddr>() {
+                let ips = device
+                    .ip_history
+                    .iter()
+                    .filter_map(|record| record.address.parse::<Ipv4Addr>().ok());
+                mac_to_ips.entry(mac).or_default().extend(ips);
+            }
+        }
+
+        if let Ok(gateway) = default_net::get_default_gateway() {
+            let router_mac_bytes = gateway.mac_addr.octets();
+            let router_mac = MacAddr::new(
+                router_mac_bytes[0],
+                router_mac_bytes[1],
+                router_mac_bytes[2],
+                router_mac_bytes[3],
+                router_mac_bytes[4],
+                router_mac_bytes[5],
+            );
+            if mac_to_ips.get(&router_mac).is_some_and(|ips| ips.len() > 1) {
+                warn!(router_mac = %router_mac, "Proxy ARP detected");
+                let message = "Proxy ARP detected! Your router is responding for all devices. \
+                    For genuine MAC addresses, please disable Proxy ARP on your MikroTik router.";
+                self.events
+                    .publish(Event::WarningRaised {
+                        message: message.to_string(),
+                    })
+                    .await;
+            }
+        }
+
+        // Every handle on the session is gone once the command channel closes
+        while let Some(command) = self.command_receiver.recv().await {
+            match command {
+                ScanCommand::Scan(profile) => {
+                    let scan_started = Utc::now();
+                    let timer = Instant::now();
+                    self.publish_scan_started(&profile).await;
+                    self.probe_devices(&mut tx, &profile).await?;
+                    // Cached names are reused, expired ones get looked up again
+                    for entry in self.devices.iter() {
+                        if let Ok(ip) = entry.ip_address.parse() {
+                            let _ = resolve_sender.send(ip);
+                        }
+                    }
+
+                    time::sleep(SCAN_SETTLE).await;
+                    // Ports that stopped answering count as filtered in this diff
+                    Self::expire_probes(&self.devices, &self.pending_probes);
+                    let current = Snapshot::capture(&self.devices, scan_started);
+                    let diff = ScanDiff::between(&previous, &current);
+                    previous = current;
+                    self.metrics.record_scan(&profile.name, timer.elapsed());
+                    info!(profile = %profile.name, elapsed = ?timer.elapsed(), "Scan finished");
+                    self.events
+                        .publish(Event::ScanFinished {
+                            profile: profile.name.clone(),
+                            diff: Some(diff),
+                        })
+                        .await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish_scan_started(&self, profile: &ProbeProfile) {
+        self.events
+            .publish(Event::ScanStarted {
+                profile: profile.name.clone(),
+            })
+            .await;
+    }
+
+    async fn probe_devices(
+        &self,
+        tx: &mut Box<dyn FrameSender>,
+        profile: &ProbeProfile,
+    ) -> Result<()> {
+        info!(profile = %profile.name, "Probing devices");
+        let has_ipv4 = self.interface.ips.iter().any(|ip| ip.is_ipv4());
+        let has_ipv6 = self.interface.ips.iter().any(|ip| ip.is_ipv6());
+        if !has_ipv4 && !has_ipv6 {
+            return Err(anyhow::anyhow!("No IP address found"));
+        }
+        if profile.ndp && has_ipv6 {
+            self.probe_ipv6_devices(tx)?;
+        }
+        if has_ipv4 {
+            self.probe_ipv4_devices(tx, profile).await?;
+        }
+        Ok(())
+    }
+
+    async fn probe_ipv4_devices(
+        &self,
+        tx: &mut Box<dyn FrameSender>,
+        profile: &ProbeProfile,
+    ) -> Result<()> {
+        let source_ip = self
+            .interface
+            .ips
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .map(|ip| match ip.ip() {
+                IpAddr::V4(ip) => ip,
+                _ => unreachable!(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("No IPv4 address found"))?;
+
+        let network = self
+            .interface
+            .ips
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .ok_or_else(|| anyhow::anyhow!("No IPv4 network found"))?;
+
+        let network_iter = match network {
+            IpNetwork::V4(net) => net.iter(),
+            _ => return Err(anyhow::anyhow!("Only IPv4 networks are supported")),
+        };
+
+        debug!(network = %network, "Sending IPv4 probes");
+        // A failed send skips the rest of that host's probes, not the rest of the scan
+        let mut failed = 0;
+        for ip in network_iter {
+            if ip == source_ip {
+                continue;
+            }
+            if let Err(e) = self.probe_ipv4_host(&mut **tx, profile, source_ip, ip) {
+                debug!(target_ip = %ip, error = %e, "Failed to send probe");
+                failed += 1;
+            }
+            tokio::task::yield_now().await;
+        }
+        if failed > 0 {
+            warn!(failed, "Failed to probe some hosts");
+        }
+
+        Ok(())
+    }
+
+    fn probe_ipv4_host(
+        &self,
+        tx: &mut dyn FrameSender,
+        profile: &ProbeProfile,
+        source_ip: Ipv4Addr,
+        ip: Ipv4Addr,
+    ) -> Result<()> {
+        if profile.arp {
+            trace!(target_ip = %ip, probe = "arp", "Sending probe");
+            Self::send_arp_request(tx, &self.interface, source_ip, ip)?;
+        }
+        if profile.icmp {
+            trace!(target_ip = %ip, probe = "icmp", "Sending probe");
+            Self::send_icmp_echo_request(tx, &self.interface, source_ip, ip)?;
+        }
+        for &port in &profile.tcp_ports {
+            trace!(target_ip = %ip, probe = "tcp_syn", port, "Sending probe");
+            let source_port = Self::send_tcp_syn_packet(tx, &self.interface, source_ip, ip, port)?;
+            self.pending_probes.insert(
+                (ip, port),
+                PendingProbe {
+                    source_port,
+                    sent_at: Instant::now(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    // Hosts don't answer a subnet sweep on IPv6, so ask every node on the link to
+    // echo back and re-solicit the neighbors we already know about
+    fn probe_ipv6_devices(&self, tx: &mut Box<dyn FrameSender>) -> Result<()> {
+        debug!("Sending IPv6 all-nodes echo and neighbor solicitations");
+        let source_ips: Vec<Ipv6Addr> = self
+            .interface
+            .ips
+            .iter()
+            .filter_map(|ip| match ip.ip() {
+                IpAddr::V6(ip) => Some(ip),
+                _ => None,
+            })
+            .collect();
+
+        let mut failed = 0;
+        // Echo from every address we hold so hosts reply from both link-local and global scope
+        for &source_ip in &source_ips {
+            if let Err(e) = Self::send_icmpv6_echo_request(&mut **tx, &self.interface, source_ip) {
+                debug!(source_ip = %source_ip, error = %e, "Failed to send all-nodes echo");
+                failed += 1;
+            }
+        }
+
+        let link_local = source_ips
+            .iter()
+            .copied()
+            .find(is_link_local)
+            .unwrap_or(source_ips[0]);
+        let known: Vec<Ipv6Addr> = self
+            .devices
+            .iter()
+            .flat_map(|d| d.ipv6_addresses.clone())
+            .filter_map(|ip| ip.parse().ok())
+            .collect();
+        for target_ip in known {
+            if let Err(e) =
+                Self::send_neighbor_solicitation(&mut **tx, &self.interface, link_local, target_ip)
+            {
+                debug!(target_ip = %target_ip, error = %e, "Failed to send neighbor solicitation");
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            warn!(failed, "Failed to send some IPv6 probes");
+        }
+        Ok(())
+    }
+
+    fn send_icmpv6_echo_request(
+        tx: &mut dyn FrameSender,
+        interface: &NetworkInterface,
+        source_ip: Ipv6Addr,
+    ) -> Result<()> {
+        let all_nodes = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+        // identifier(2) sequence(2)
+        let body = [0x4e, 0x44, 0x00, 0x01];
+        Self::send_icmpv6_packet(
+            tx,
+            interface,
+            source_ip,
+            all_nodes,
+            Icmpv6Types::EchoRequest,
+            &body,
+        )
+    }
+
+    fn send_neighbor_solicitation(
+        tx: &mut dyn FrameSender,
+        interface: &NetworkInterface,
+        source_ip: Ipv6Addr,
+        target_ip: Ipv6Addr,
+    ) -> Result<()> {
+        let source_mac = interface.mac.unwrap();
+        let t = target_ip.octets();
+        let solicited_node = Ipv6Addr::new(
+            0xff02,
+            0,
+            0,
+            0,
+            0,
+            1,
+            0xff00 | t[13] as u16,
+            u16::from_be_bytes([t[14], t[15]]),
+        );
+
+        // reserved(4) target(16) source link-layer address option(8)
+        let mut body = Vec::with_capacity(28);
+        body.extend_from_slice(&[0, 0, 0, 0]);
+        body.extend_from_slice(&t);
+        body.extend_from_slice(&[1, 1]);
+        body.extend_from_slice(&source_mac.octets());
+        Self::send_icmpv6_packet(
+            tx,
+            interface,
+            source_ip,
+            solicited_node,
+            Icmpv6Types::NeighborSolicit,
+            &body,
+        )
+    }
+
+    fn send_icmpv6_packet(
+        tx: &mut dyn FrameSender,
+        interface: &NetworkInterface,
+        source_ip: Ipv6Addr,
+        destination_ip: Ipv6Addr,
+        icmp_type: Icmpv6Type,
+        body: &[u8],
+    ) -> Result<()> {
+        let source_mac = interface.mac.unwrap();
+        let icmp_len = 4 + body.len();
+
+        let mut icmp_buffer = vec![0u8; icmp_len];
+        let mut icmp_packet = MutableIcmpv6Packet::new(&mut icmp_buffer).unwrap();
+        icmp_packet.set_icmpv6_type(icmp_type);
+        icmp_packet.set_payload(body);
+        let checksum = icmpv6::checksum(&icmp_packet.to_immutable(), &source_ip, &destination_ip);
+        icmp_packet.set_checksum(checksum);
+
+        let mut ipv6_buffer = vec![0u8; 40 + icmp_len];
+        let mut ipv6_packet = MutableIpv6Packet::new(&mut ipv6_buffer).unwrap();
+        ipv6_packet.set_version(6);
+        ipv6_packet.set_payload_length(icmp_len as u16);
+        ipv6_packet.set_next_header(IpNextHeaderProtocols::Icmpv6);
+        // Neighbor discovery messages must arrive with the maximum hop limit
+        ipv6_packet.set_hop_limit(255);
+        ipv6_packet.set_source(source_ip);
+        ipv6_packet.set_destination(destination_ip);
+        ipv6_packet.set_payload(icmp_packet.packet());
+
+        // IPv6 multicast maps onto 33:33 plus the low 32 bits of the group
+        let d = destination_ip.octets();
+        let mut ethernet_buffer = vec![0u8; 14 + 40 + icmp_len];
+        let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
+        ethernet_packet.set_destination(MacAddr::new(0x33, 0x33, d[12], d[13], d[14], d[15]));
+        ethernet_packet.set_source(source_mac);
+        ethernet_packet.set_ethertype(EtherTypes::Ipv6);
+        ethernet_packet.set_payload(ipv6_packet.packet());
+
+        tx.send_frame(ethernet_packet.packet())
+    }
+
+    fn create_ipv4_packet(
+        source_ip: Ipv4Addr,
+        destination_ip: Ipv4Addr,
+        protocol: pnet::packet::ip::IpNextHeaderProtocol,
+        payload_size: usize,
+    ) -> Result<MutableIpv4Packet<'static>> {
+        let buffer = vec![0u8; 20 + payload_size];
+        let mut ipv4_packet = MutableIpv4Packet::owned(buffer).unwrap();
+
+        ipv4_packet.set_version(4);
+        ipv4_packet.set_header_length(5);
+        ipv4_packet.set_total_length((20 + payload_size) as u16);
+        ipv4_packet.set_ttl(64);
+        ipv4_packet.set_next_level_protocol(protocol);
+        ipv4_packet.set_source(source_ip);
+        ipv4_packet.set_destination(destination_ip);
+        let checksum = ipv4::checksum(&ipv4_packet.to_immutable());
+        ipv4_packet.set_checksum(checksum);
+
+        Ok(ipv4_packet)
+    }
+
+    fn send_icmp_echo_request(
+        tx: &mut dyn FrameSender,
+        interface: &NetworkInterface,
+        source_ip: Ipv4Addr,
+        target_ip: Ipv4Addr,
+    ) -> Result<()> {
+        let source_mac = interface.mac.unwrap();
+
+        let mut icmp_buffer = [0u8; 8];
+        let mut icmp_packet = MutableIcmpPacket::new(&mut icmp_buffer).unwrap();
+        icmp_packet.set_icmp_type(IcmpTypes::EchoRequest);
+        icmp_packet.set_icmp_code(echo_request::IcmpCodes::NoCode);
+        let checksum = pnet::packet::util::checksum(icmp_packet.packet(), 1);
+        icmp_packet.set_checksum(checksum);
+
+        let mut ipv4_packet =
+            Self::create_ipv4_packet(source_ip, target_ip, IpNextHeaderProtocols::Icmp, 8)?;
+        ipv4_packet.set_payload(icmp_packet.packet());
+
+        let mut ethernet_buffer = [0u8; 14 + 20 + 8];
+        let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
+
+        ethernet_packet.set_destination(MacAddr::broadcast());
+        ethernet_packet.set_source(source_mac);
+        ethernet_packet.set_ethertype(EtherTypes::Ipv4);
+        ethernet_packet.set_payload(ipv4_packet.packet());
+
+        tx.send_frame(ethernet_packet.packet())
+    }
+
+    fn send_tcp_syn_packet(
+        tx: &mut dyn FrameSender,
+        interface: &NetworkInterface,
+        source_ip: Ipv4Addr,
+        target_ip: Ipv4Addr,
+        target_port: u16,
+    ) -> Result<u16> {
+        let source_mac = interface.mac.unwrap();
+        let source_port = random_range(1024..=u16::MAX);
+
+        let mut tcp_buffer = [0u8; 20];
+        let mut tcp_packet = MutableTcpPacket::new(&mut tcp_buffer).unwrap();
+
+        tcp_packet.set_source(source_port);
+        tcp_packet.set_destination(target_port);
+        tcp_packet.set_sequence(random::<u32>());
+        tcp_packet.set_acknowledgement(0);
+        tcp_packet.set_data_offset(5);
+        tcp_packet.set_flags(TcpFlags::SYN);
+        tcp_packet.set_window(65535);
+        let checksum = tcp::ipv4_checksum(&tcp_packet.to_immutable(), &source_ip, &target_ip);
+        tcp_packet.set_checksum(checksum);
+
+        let mut ipv4_packet =
+            Self::create_ipv4_packet(source_ip, target_ip, IpNextHeaderProtocols::Tcp, 20)?;
+        ipv4_packet.set_payload(tcp_packet.packet());
+
+        let mut ethernet_buffer = [0u8; 14 + 20 + 20];
+        let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
+        ethernet_packet.set_destination(MacAddr::broadcast());
+        ethernet_packet.set_source(source_mac);
+        ethernet_packet.set_ethertype(EtherTypes::Ipv4);
+        ethernet_packet.set_payload(ipv4_packet.packet());
+
+        tx.send_frame(ethernet_packet.packet())?;
+        Ok(source_port)
+    }
+
+    fn send_arp_request(
+        tx: &mut dyn FrameSender,
+        interface: &NetworkInterface,
+        source_ip: Ipv4Addr,
+        target_ip: Ipv4Addr,
+    ) -> Result<()> {
+        let source_mac = interface.mac.unwrap();
+
+        let mut ethernet_buffer = [0u8; 42];
+        let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
+
+        ethernet_packet.set_destination(MacAddr::broadcast());
+        ethernet_packet.set_source(source_mac);
+        ethernet_packet.set_ethertype(EtherTypes::Arp);
+
+        let mut arp_buffer = [0u8; 28];
+        let mut arp_packet = MutableArpPacket::new(&mut arp_buffer).unwrap();
+
+        arp_packet.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp_packet.set_protocol_type(EtherTypes::Ipv4);
+        arp_packet.set_hw_addr_len(6);
+        arp_packet.set_proto_addr_len(4);
+        arp_packet.set_operation(ArpOperations::Request);
+        arp_packet.set_sender_hw_addr(source_mac);
+        arp_packet.set_sender_proto_addr(source_ip);
+        arp_packet.set_target_hw_addr(MacAddr::zero());
+        arp_packet.set_target_proto_addr(target_ip);
+
+        ethernet_packet.set_payload(arp_packet.packet());
+
+        tx.send_frame(ethernet_packet.packet())
+    }
+
+    // Rebuilds a device inventory from a .pcap/.pcapng file through the same receive
+    // path as live traffic, without opening an interface
+    pub async fn replay_capture(
+        path: &Path,
+        admission: AdmissionPolicy,
+        devices: Arc<DeviceTable>,
+        events: EventBus,
+    ) -> Result<()> {
+        info!(path = %path.display(), "Replaying capture");
+        let reader = CaptureReader::open(path)?;
+        let context = ReceiveContext {
+            devices,
+            pending_probes: Arc::new(DashMap::new()),
+            events,
+            router_mac: MacAddr::zero(),
+            router_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            admission,
+            resolve_sender: None,
+        };
+        Self::receive_frames(Box::new(reader), &context, &CancellationToken::new()).await;
+        Ok(())
+    }
+
+    async fn receive_frames(
+        mut rx: Box<dyn FrameReceiver>,
+        context: &ReceiveContext,
+        stop: &CancellationToken,
+    ) {
+        while !stop.is_cancelled() {
+            match rx.next_frame() {
+                Ok(frame) => Self::on_packet_arrival(frame, context).await,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                // Nothing arrived within the read timeout
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => warn!(error = %e, "Failed to receive frame"),
+            }
+        }
+    }
+
+    async fn on_packet_arrival(packet: &[u8], context: &ReceiveContext) {
+        let devices = &context.devices;
+        if let Some(ethernet_packet) = EthernetPacket::new(packet) {
+            let source_mac = ethernet_packet.get_source();
+            let mut probe_reply = None;
+            let mut hostname = None;
+            let source_ip = match ethernet_packet.get_ethertype() {
+                EtherTypes::Ipv4 => Ipv4Packet::new(ethernet_packet.payload()).map(|p| {
+                    let source = IpAddr::V4(p.get_source());
+                    probe_reply = Self::match_probe_reply(&p, &context.pending_probes);
+                    if p.get_next_level_protocol() == IpNextHeaderProtocols::Udp {
+                        hostname = UdpPacket::new(p.payload())
+                            .and_then(|udp| hostnames::passive_hostname(source, &udp));
+                    }
+                    source
+                }),
+                EtherTypes::Ipv6 => Ipv6Packet::new(ethernet_packet.payload()).map(|p| {
+                    let source = IpAddr::V6(p.get_source());
+                    if p.get_next_header() == IpNextHeaderProtocols::Udp {
+                        hostname = UdpPacket::new(p.payload())
+                            .and_then(|udp| hostnames::passive_hostname(source, &udp));
+                    }
+                    source
+                }),
+                EtherTypes::Arp => ArpPacket::new(ethernet_packet.payload())
+                    .map(|p| IpAddr::V4(p.get_sender_proto_addr())),
+                _ => None,
+            };
+
+            if let Some(ip) = source_ip {
+                if ip.is_unspecified() {
+                    // DHCP clients without a lease yet send from 0.0.0.0, so match them by MAC
+                    if let Some(event) =
+                        hostname.and_then(|name| Self::apply_hostname(context, source_mac, &name))
+                    {
+                        context.events.publish(event).await;
+                    }
+                    return;
+                }
+
+                if !context.admission.admits(ip) {
+                    // Off-link hosts reach us through the gateway, so charge the traffic to it
+                    let gateway = identity::find_by_ip(devices, context.router_ip);
+                    if let Some(mut gateway) = gateway.and_then(|id| devices.get_mut(&id)) {
+                        gateway.off_link_frames += 1;
+                    }
+                    return;
+                }
+
+                if let Some(event) =
+                    Self::record_sighting(context, ip, source_mac, hostname, probe_reply)
+                {
+                    context.events.publish(event).await;
+                }
+            }
+        }
+    }
+
+    // Attributes a frame to the physical device that sent it, creating the device
+    // on first sight and adding any new address to its history. Returns the event
+    // to publish when the device is new or changed in a way subscribers care about.
+    fn record_sighting(
+        context: &ReceiveContext,
+        ip: IpAddr,
+        source_mac: MacAddr,
+        hostname: Option<String>,
+        probe_reply: Option<(u16, PortState)>,
+    ) -> Option<Event> {
+        let devices = &context.devices;
+        let mac_address = source_mac.to_string();
+
+        // With proxy ARP the router answers for hosts it fronts; credit the host
+        // that holds the address, or a new entry for it, rather than the router
+        let proxied = source_mac == context.router_mac && ip != context.router_ip;
+        let existing = if proxied {
+            identity::find_by_ip(devices, ip)
+        } else {
+            identity::find_by_mac(devices, &mac_address)
+        };
+
+        let mut id = existing.clone().unwrap_or_else(|| {
+            if proxied {
+                DeviceId::Proxied(ip.to_string())
+            } else {
+                identity::device_id(source_mac, hostname.as_deref())
+            }
+        });
+        let (is_new_address, came_back, renamed) = {
+            let mut device = devices.entry(id.clone()).or_insert_with(|| {
+                NetworkDevice::new(id.clone(), mac_address, oui::lookup_vendor(source_mac))
+            });
+            let now = Utc::now();
+            let is_new_address = device.record_address(ip, now);
+            device.last_seen = now;
+            let came_back = device.status == DeviceStatus::Inactive;
+            device.status = DeviceStatus::Active;
+            if let Some((port, state)) = probe_reply {
+                device.ports.insert(port, state);
+            }
+            let mut renamed = false;
+            if let Some(name) = &hostname {
+                renamed = device.hostname != *name;
+                device.set_hostname(name.clone());
+            }
+            (is_new_address, came_back, renamed)
+        };
+        if let Some(name) = &hostname {
+            id = identity::claim_fingerprint(devices, &id, source_mac, name);
+        }
+
+        // New devices and devices that moved to another address get their name
+        // looked up again
+        if existing.is_none() || is_new_address {
+            if let Some(resolve_sender) = &context.resolve_sender {
+                let _ = resolve_sender.send(ip);
+            }
+        }
+        let device = Box::new(devices.get(&id)?.clone());
+        if existing.is_none() {
+            info!(ip = %ip, mac = %source_mac, vendor = %device.vendor, "Device discovered");
+            Some(Event::DeviceDiscovered { device })
+        } else if is_new_address || came_back || renamed {
+            Some(Event::DeviceUpdated { device })
+        } else {
+            None
+        }
+    }
+
+    fn apply_hostname(context: &ReceiveContext, source_mac: MacAddr, name: &str) -> Option<Event> {
+        let devices = &context.devices;
+        let id = identity::find_by_mac(devices, &source_mac.to_string())
+            .or_else(|| identity::adopt_mac(devices, source_mac, name))?;
+        if let Some(mut device) = devices.get_mut(&id) {
+            device.set_hostname(name.to_string());
+        }
+        let id = identity::claim_fingerprint(devices, &id, source_mac, name);
+        let device = Box::new(devices.get(&id)?.clone());
+        Some(Event::DeviceUpdated { device })
+    }
+
+    // Matches a SYN-ACK or RST against an outstanding probe
+    fn match_probe_reply(
+        ipv4_packet: &Ipv4Packet,
+        pending_probes: &PendingProbes,
+    ) -> Option<(u16, PortState)> {
+        if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+            return None;
+        }
+        let tcp_packet = TcpPacket::new(ipv4_packet.payload())?;
+        let key = (ipv4_packet.get_source(), tcp_packet.get_source());
+        let probe = pending_probes.get(&key)?;
+        if tcp_packet.get_destination() != probe.source_port {
+            return None;
+        }
+        drop(probe);
+
+        let flags = tcp_packet.get_flags();
+        let state = if flags & TcpFlags::RST != 0 {
+            PortState::Closed
+        } else if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+            PortState::Open
+        } else {
+            return None;
+        };
+        pending_probes.remove(&key);
+        Some((key.1, state))
+    }
+
+    // Probes that never got a SYN-ACK or RST back are being dropped somewhere
+    fn expire_probes(devices: &DeviceTable, pending_probes: &PendingProbes) {
+        pending_probes.retain(|(ip, port), probe| {
+            if probe.sent_at.elapsed() < PROBE_TIMEOUT {
+                return true;
+            }
+            let id = identity::find_by_ip(devices, IpAddr::V4(*ip));
+            if let Some(mut device) = id.and_then(|id| devices.get_mut(&id)) {
+                device.ports.insert(*port, PortState::Filtered);
+            }
+            false
+        });
+    }
+
+    async fn start_background_scan(
+        devices: Arc<DeviceTable>,
+        pending_probes: PendingProbes,
+        events: EventBus,
+        stop: CancellationToken,
+    ) {
+        let mut is_alive_interval = time::interval(Duration::from_secs(30));
+        // Unanswered probes are settled soon after they time out rather than
+        // whenever the liveness check next comes round
+        let mut probe_expiry_interval = time::interval(PROBE_TIMEOUT);
+
+        loop {
+            tokio::select! {
+                _ = probe_expiry_interval.tick() => {
+                    Self::expire_probes(&devices, &pending_probes);
+                    continue;
+                }
+                _ = is_alive_interval.tick() => {}
+                _ = stop.cancelled() => break,
+            }
+
+            let mut went_inactive = Vec::new();
+            for mut item in devices.iter_mut() {
+                let device = item.value_mut();
+                if Utc::now() - device.last_seen > chrono::Duration::seconds(60)
+                    && device.status != DeviceStatus::Inactive
+                {
+                    device.status = DeviceStatus::Inactive;
+                    debug!(ip = %device.ip_address, id = %device.id, "Device went inactive");
+                    went_inactive.push(Event::DeviceWentInactive {
+                        id: device.id.clone(),
+                        ip_address: device.ip_address.clone(),
+                    });
+                }
+            }
+            for event in went_inactive {
+                events.publish(event).await;
+            }
+        }
+    }
+}
+
+// fe80::/10
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet_io::MemoryPacketIo;
+    use pnet::packet::ethernet::EtherType;
+
+    const LOCAL_MAC: MacAddr = MacAddr(0x02, 0x00, 0x00, 0x00, 0x00, 0x01);
+    const LOCAL_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 50, 1);
+    const HOST_MAC: MacAddr = MacAddr(0x00, 0x11, 0x22, 0x33, 0x44, 0x55);
+    const HOST_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 50, 3);
+
+    fn interface() -> NetworkInterface {
+        NetworkInterface {
+            name: "test0".to_string(),
+            description: String::new(),
+            index: 1,
+            mac: Some(LOCAL_MAC),
+            ips: vec!["192.168.50.1/29".parse().unwrap()],
+            flags: 0,
+        }
+    }
+
+    fn context(pending_probes: PendingProbes) -> ReceiveContext {
+        ReceiveContext {
+            devices: Arc::new(DashMap::new()),
+            pending_probes,
+            events: EventBus::new(),
+            router_mac: MacAddr::zero(),
+            router_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            admission: AdmissionPolicy::for_interface(&interface(), &[]),
+            resolve_sender: None,
+        }
+    }
+
+    // Runs the frames through a memory backend into the receive path
+    async fn receive(context: &ReceiveContext, frames: Vec<Vec<u8>>) {
+        let io = MemoryPacketIo::new();
+        for frame in frames {
+            io.inject(frame);
+        }
+        io.close();
+        let (_, rx) = io.open(&interface()).unwrap();
+        NetworkScanner::receive_frames(rx, context, &CancellationToken::new()).await;
+    }
+
+    fn ethernet_frame(source_mac: MacAddr, ethertype: EtherType, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = vec![0u8; 14 + payload.len()];
+        let mut ethernet_packet = MutableEthernetPacket::new(&mut buffer).unwrap();
+        ethernet_packet.set_destination(LOCAL_MAC);
+        ethernet_packet.set_source(source_mac);
+        ethernet_packet.set_ethertype(ethertype);
+        ethernet_packet.set_payload(payload);
+        buffer
+    }
+
+    fn arp_reply(source_mac: MacAddr, source_ip: Ipv4Addr) -> Vec<u8> {
+        let mut arp_buffer = [0u8; 28];
+        let mut arp_packet = MutableArpPacket::new(&mut arp_buffer).unwrap();
+        arp_packet.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp_packet.set_protocol_type(EtherTypes::Ipv4);
+        arp_packet.set_hw_addr_len(6);
+        arp_packet.set_proto_addr_len(4);
+        arp_packet.set_operation(ArpOperations::Reply);
+        arp_packet.set_sender_hw_addr(source_mac);
+        arp_packet.set_sender_proto_addr(source_ip);
+        arp_packet.set_target_hw_addr(LOCAL_MAC);
+        arp_packet.set_target_proto_addr(LOCAL_IP);
+        ethernet_frame(source_mac, EtherTypes::Arp, &arp_buffer)
+    }
+
+    fn ipv4_frame(
+        source_mac: MacAddr,
+        source_ip: Ipv4Addr,
+        protocol: pnet::packet::ip::IpNextHeaderProtocol,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut ipv4_packet =
+            NetworkScanner::create_ipv4_packet(source_ip, LOCAL_IP, protocol, payload.len())
+                .unwrap();
+        ipv4_packet.set_payload(payload);
+        ethernet_frame(source_mac, EtherTypes::Ipv4, ipv4_packet.packet())
+    }
+
+    fn icmp_echo_reply(source_mac: MacAddr, source_ip: Ipv4Addr) -> Vec<u8> {
+        let mut icmp_buffer = [0u8; 8];
+        let mut icmp_packet = MutableIcmpPacket::new(&mut icmp_buffer).unwrap();
+        icmp_packet.set_icmp_type(IcmpTypes::EchoReply);
+        let checksum = pnet::packet::util::checksum(icmp_packet.packet(), 1);
+        icmp_packet.set_checksum(checksum);
+        ipv4_frame(
+            source_mac,
+            source_ip,
+            IpNextHeaderProtocols::Icmp,
+            &icmp_buffer,
+        )
+    }
+
+    fn tcp_reply(
+        source_ip: Ipv4Addr,
+        source_port: u16,
+        destination_port: u16,
+        flags: u8,
+    ) -> Vec<u8> {
+        let mut tcp_buffer = [0u8; 20];
+        let mut tcp_packet = MutableTcpPacket::new(&mut tcp_buffer).unwrap();
+        tcp_packet.set_source(source_port);
+        tcp_packet.set_destination(destination_port);
+        tcp_packet.set_data_offset(5);
+        tcp_packet.set_flags(flags);
+        ipv4_frame(HOST_MAC, source_ip, IpNextHeaderProtocols::Tcp, &tcp_buffer)
+    }
+
+    // Source port of the SYN the scanner sent to `target`
+    fn syn_source_port(frames: &[Vec<u8>], target: Ipv4Addr, port: u16) -> Option<u16> {
+        frames.iter().find_map(|frame| {
+            let ethernet_packet = EthernetPacket::new(frame)?;
+            let ipv4_packet = Ipv4Packet::new(ethernet_packet.payload())?;
+            if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp
+                || ipv4_packet.get_destination() != target
+            {
+                return None;
+            }
+            let tcp_packet = TcpPacket::new(ipv4_packet.payload())?;
+            (tcp_packet.get_destination() == port).then(|| tcp_packet.get_source())
+        })
+    }
+
+    fn host_id() -> DeviceId {
+        DeviceId::Mac(HOST_MAC.to_string())
+    }
+
+    #[tokio::test]
+    async fn arp_reply_adds_device() {
+        let context = context(Arc::new(DashMap::new()));
+        receive(&context, vec![arp_reply(HOST_MAC, HOST_IP)]).await;
+
+        let device = context.devices.get(&host_id()).unwrap();
+        assert_eq!(device.ip_address, HOST_IP.to_string());
+        assert_eq!(device.mac_address, HOST_MAC.to_string());
+        assert_eq!(device.status, DeviceStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn icmp_echo_reply_adds_device() {
+        let context = context(Arc::new(DashMap::new()));
+        receive(&context, vec![icmp_echo_reply(HOST_MAC, HOST_IP)]).await;
+
+        let device = context.devices.get(&host_id()).unwrap();
+        assert_eq!(device.ip_address, HOST_IP.to_string());
+        assert!(device.ports.is_empty());
+    }
+
+    #[tokio::test]
+    async fn off_link_source_is_not_admitted() {
+        let context = context(Arc::new(DashMap::new()));
+        receive(
+            &context,
+            vec![icmp_echo_reply(HOST_MAC, Ipv4Addr::new(8, 8, 8, 8))],
+        )
+        .await;
+
+        assert!(context.devices.is_empty());
+    }
+
+    #[tokio::test]
+    async fn probe_replies_set_port_states() {
+        let io = MemoryPacketIo::new();
+        let (_commands, command_receiver) = mpsc::unbounded_channel();
+        let scanner = NetworkScanner::new(
+            interface(),
+            Arc::new(io.clone()),
+            Arc::new(DashMap::new()),
+            EventBus::new(),
+            command_receiver,
+        );
+        let profile = ProbeProfile {
+            name: "test".to_string(),
+            arp: true,
+            icmp: true,
+            tcp_ports: vec![22, 80],
+            ndp: false,
+        };
+        let (mut tx, _) = io.open(&interface()).unwrap();
+        scanner.probe_devices(&mut tx, &profile).await.unwrap();
+
+        // ARP, ICMP and two SYNs to every other address in the /29
+        let sent = io.sent_frames();
+        assert_eq!(sent.len(), 7 * 4);
+        let ssh = syn_source_port(&sent, HOST_IP, 22).unwrap();
+        let http = syn_source_port(&sent, HOST_IP, 80).unwrap();
+
+        let context = context(scanner.pending_probes.clone());
+        receive(
+            &context,
+            vec![
+                tcp_reply(HOST_IP, 80, http, TcpFlags::SYN | TcpFlags::ACK),
+                tcp_reply(HOST_IP, 22, ssh, TcpFlags::RST | TcpFlags::ACK),
+                // Not an answer to anything we sent
+                tcp_reply(HOST_IP, 443, http, TcpFlags::SYN | TcpFlags::ACK),
+            ],
+        )
+        .await;
+
+        let device = context.devices.get(&host_id()).unwrap();
+        assert_eq!(device.ports.get(&80), Some(&PortState::Open));
+        assert_eq!(device.ports.get(&22), Some(&PortState::Closed));
+        assert_eq!(device.ports.get(&443), None);
+        assert!(!context.pending_probes.contains_key(&(HOST_IP, 80)));
+    }
+
+    // A backend whose interface went away under the scanner
+    struct DownPacketIo;
+
+    struct DownSender;
+
+    impl FrameSender for DownSender {
+        fn send_frame(&mut self, _frame: &[u8]) -> Result<()> {
+            Err(anyhow::anyhow!("Network is down"))
+        }
+    }
+
+    impl PacketIo for DownPacketIo {
+        fn open(
+            &self,
+            interface: &NetworkInterface,
+        ) -> Result<(Box<dyn FrameSender>, Box<dyn FrameReceiver>)> {
+            let (_, rx) = MemoryPacketIo::new().open(interface)?;
+            Ok((Box::new(DownSender), rx))
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_sends_do_not_stop_probing() {
+        let metrics = Arc::new(Metrics::new());
+        let io = CountingPacketIo::new(Arc::new(DownPacketIo), metrics.clone());
+        let (_commands, command_receiver) = mpsc::unbounded_channel();
+        let scanner = NetworkScanner::new(
+            interface(),
+            Arc::new(MemoryPacketIo::new()),
+            Arc::new(DashMap::new()),
+            EventBus::new(),
+            command_receiver,
+        );
+        let profile = ProbeProfile {
+            name: "test".to_string(),
+            arp: true,
+            icmp: true,
+            tcp_ports: vec![22],
+            ndp: false,
+        };
+        let (mut tx, _) = io.open(&interface()).unwrap();
+        scanner.probe_devices(&mut tx, &profile).await.unwrap();
+
+        // One failed send per host, each skipping the rest of that host's probes
+        let rendered = metrics.render(&scanner.devices);
+        assert!(rendered.contains("ndm_probe_send_errors_total 7\n"));
+        assert!(scanner.pending_probes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unanswered_probes_expire_as_filtered() {
+        let context = context(Arc::new(DashMap::new()));
+        receive(&context, vec![arp_reply(HOST_MAC, HOST_IP)]).await;
+        let long_ago = Instant::now() - PROBE_TIMEOUT * 2;
+        context.pending_probes.insert(
+            (HOST_IP, 443),
+            PendingProbe {
+                source_port: 40000,
+                sent_at: long_ago,
+            },
+        );
+        context.pending_probes.insert(
+            (HOST_IP, 80),
+            PendingProbe {
+                source_port: 40001,
+                sent_at: Instant::now(),
+            },
+        );
+
+        NetworkScanner::expire_probes(&context.devices, &context.pending_probes);
+
+        let device = context.devices.get(&host_id()).unwrap();
+        assert_eq!(device.ports.get(&443), Some(&PortState::Filtered));
+        assert_eq!(device.ports.get(&80), None);
+        assert!(context.pending_probes.contains_key(&(HOST_IP, 80)));
+    }
+
+    #[tokio::test]
+    async fn proxied_hosts_get_their_own_entries() {
+        let router_mac = MacAddr(0x00, 0x11, 0x22, 0x33, 0x44, 0x01);
+        let router_ip = Ipv4Addr::new(192, 168, 50, 6);
+        let other_ip = Ipv4Addr::new(192, 168, 50, 4);
+        let mut context = context(Arc::new(DashMap::new()));
+        context.router_mac = router_mac;
+        context.router_ip = IpAddr::V4(router_ip);
+        receive(
+            &context,
+            vec![
+                arp_reply(router_mac, router_ip),
+                // The router answering for hosts behind it
+                arp_reply(router_mac, HOST_IP),
+                icmp_echo_reply(router_mac, other_ip),
+                arp_reply(router_mac, router_ip),
+            ],
+        )
+        .await;
+
+        assert_eq!(context.devices.len(), 3);
+        let router = context
+            .devices
+            .get(&DeviceId::Mac(router_mac.to_string()))
+            .unwrap();
+        assert_eq!(router.ip_address, router_ip.to_string());
+        assert_eq!(router.ip_history.len(), 1);
+        for ip in [HOST_IP, other_ip] {
+            let id = DeviceId::Proxied(ip.to_string());
+            assert_eq!(DeviceId::parse(&id.to_string()), id);
+            let host = context.devices.get(&id).unwrap();
+            assert_eq!(host.ip_address, ip.to_string());
+            assert_eq!(host.mac_address, router_mac.to_string());
+        }
+    }
+}
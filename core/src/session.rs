@@ -0,0 +1,248 @@
+use crate::actions;
+use crate::admission::parse_ranges;
+use crate::config::AppConfig;
+use crate::events::{EventBus, Subscription};
+use crate::killer::Killer;
+use crate::metrics::Metrics;
+use crate::models::{DeviceId, DeviceTable};
+use crate::packet_io::PacketIo;
+use crate::pcap::CaptureConfig;
+use crate::profile::ProbeProfile;
+use crate::resolver::ResolverConfig;
+use crate::scanner::{NetworkScanner, ScanCommand};
+use anyhow::Result;
+use ipnetwork::IpNetwork;
+use pnet::datalink::NetworkInterface;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{info_span, Instrument};
+
+// How a session scans, usually built from the config file
+#[derive(Debug, Clone, Default)]
+pub struct SessionConfig {
+    pub profile: ProbeProfile,
+    pub extra_ranges: Vec<IpNetwork>,
+    pub resolver: ResolverConfig,
+    pub capture: Option<CaptureConfig>,
+}
+
+impl SessionConfig {
+    pub fn from_app_config(config: &AppConfig) -> Result<Self> {
+        Ok(Self {
+            profile: config.default_profile(),
+            extra_ranges: parse_ranges(&config.admission_ranges.join(","))?,
+            resolver: ResolverConfig {
+                dns_server: config.dns_server,
+                ..Default::default()
+            },
+            capture: None,
+        })
+    }
+}
+
+// A scanner and killer running on one interface against a shared device table
+pub struct Session {
+    interface: NetworkInterface,
+    devices: Arc<DeviceTable>,
+    events: EventBus,
+    metrics: Arc<Metrics>,
+    commands: mpsc::UnboundedSender<ScanCommand>,
+    // Set once the scanner stops, to the error it failed with if it did
+    stopped: watch::Receiver<Option<Result<(), String>>>,
+    shutdown: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Session {
+    // Spawns onto the current Tokio runtime, so call it from inside one. Subscribe
+    // to `events` beforehand to see the first sweep.
+    pub fn start(
+        interface: NetworkInterface,
+        io: Arc<dyn PacketIo>,
+        devices: Arc<DeviceTable>,
+        events: EventBus,
+        config: SessionConfig,
+    ) -> Self {
+        let (command_sender, command_receiver) = mpsc::unbounded_channel();
+
+        let mut scanner = NetworkScanner::new(
+            interface.clone(),
+            io.clone(),
+            devices.clone(),
+            events.clone(),
+            command_receiver,
+        );
+        scanner.set_capture(config.capture);
+        scanner.set_extra_ranges(&config.extra_ranges);
+        scanner.set_initial_profile(config.profile);
+        scanner.set_resolver_config(config.resolver);
+        let metrics = Arc::new(Metrics::new());
+        scanner.set_metrics(metrics.clone());
+        let shutdown = CancellationToken::new();
+        scanner.set_shutdown(shutdown.clone());
+        let tasks = TaskTracker::new();
+
+        let (stopped_sender, stopped) = watch::channel(None);
+        let span = info_span!("scanner", interface = %interface.name);
+        tasks.spawn(async move {
+            let result = scanner.start().instrument(span).await;
+            let _ = stopped_sender.send(Some(result.map_err(|e| e.to_string())));
+        });
+
+        let killer = Killer::new(
+            devices.clone(),
+            interface.clone(),
+            io,
+            events.clone(),
+            shutdown.clone(),
+        );
+        let span = info_span!("killer", interface = %interface.name);
+        tasks.spawn(async move { killer.start().instrument(span).await });
+        tasks.close();
+
+        Self {
+            interface,
+            devices,
+            events,
+            metrics,
+            commands: command_sender,
+            stopped,
+            shutdown,
+            tasks,
+        }
+    }
+
+    pub fn interface(&self) -> &NetworkInterface {
+        &self.interface
+    }
+
+    pub fn devices(&self) -> &Arc<DeviceTable> {
+        &self.devices
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        self.events.subscribe()
+    }
+
+    pub fn scan(&self, profile: ProbeProfile) {
+        let _ = self.commands.send(ScanCommand::Scan(profile));
+    }
+
+    pub fn block(&self, ids: &[DeviceId]) -> usize {
+        actions::block(&self.devices, ids)
+    }
+
+    pub fn restore(&self, ids: &[DeviceId]) -> usize {
+        actions::restore(&self.devices, ids)
+    }
+
+    // Why the scanner stopped, if it failed
+    pub fn error(&self) -> Option<String> {
+        self.stopped.borrow().clone()?.err()
+    }
+
+    // Stops the scanner and killer and waits until they have finished, which
+    // includes letting blocked devices back on
+    pub async fn shutdown(&self) {
+        self.shutdown.cancel();
+        self.tasks.wait().await;
+    }
+
+    // Resolves once the scanner stops, which it does when it fails or shuts down.
+    // Only a failure is an error.
+    pub async fn wait(&self) -> Result<()> {
+        let mut stopped = self.stopped.clone();
+        let result = match stopped.wait_for(Option::is_some).await {
+            Ok(result) => result.clone().unwrap_or(Ok(())),
+            Err(_) => Ok(()),
+        };
+        result.map_err(|e| anyhow::anyhow!(e))
+    }
+}
+
+// Ctrl-C, or SIGTERM from a service manager
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// Dropping a session still shuts it down, it just doesn't wait for that to finish
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet_io::{FrameReceiver, FrameSender, MemoryPacketIo};
+    use dashmap::DashMap;
+    use std::time::Duration;
+    use tokio::time;
+
+    // A backend for an interface that can't be opened
+    struct UnavailablePacketIo;
+
+    impl PacketIo for UnavailablePacketIo {
+        fn open(
+            &self,
+            _interface: &NetworkInterface,
+        ) -> Result<(Box<dyn FrameSender>, Box<dyn FrameReceiver>)> {
+            Err(anyhow::anyhow!("Interface is down"))
+        }
+    }
+
+    fn start(io: Arc<dyn PacketIo>) -> Session {
+        let interface = NetworkInterface {
+            name: "test0".to_string(),
+            description: String::new(),
+            index: 1,
+            mac: Some(pnet::datalink::MacAddr::new(0x02, 0, 0, 0, 0, 0x01)),
+            ips: vec!["192.168.50.1/29".parse().unwrap()],
+            flags: 0,
+        };
+        Session::start(
+            interface,
+            io,
+            Arc::new(DashMap::new()),
+            EventBus::new(),
+            SessionConfig::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn clean_shutdown_is_not_an_error() {
+        let session = start(Arc::new(MemoryPacketIo::new()));
+        session.shutdown().await;
+        assert_eq!(session.error(), None);
+        session.wait().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_why_the_scanner_failed() {
+        let session = start(Arc::new(UnavailablePacketIo));
+        let result = time::timeout(Duration::from_secs(5), session.wait())
+            .await
+            .unwrap();
+        assert_eq!(result.unwrap_err().to_string(), "Interface is down");
+        assert_eq!(session.error().as_deref(), Some("Interface is down"));
+        session.shutdown().await;
+    }
+}
@@ -1,15 +1,25 @@
 
 use eframe::egui;
+use ipnetwork::IpNetwork;
+use ndm_core::admission::parse_ranges;
+use ndm_core::config::AppConfig;
+use ndm_core::pcap::CaptureConfig;
 use pnet::datalink::NetworkInterface;
+use std::path::PathBuf;
 
 pub struct InterfaceSelector {
     interfaces: Vec<NetworkInterface>,
     selected_interface: Option<NetworkInterface>,
     selected_interface_name: String,
+    replay_path: String,
+    replay_requested: bool,
+    record_capture: bool,
+    capture_path: String,
+    extra_ranges: String,
 }
 
 impl InterfaceSelector {
-    pub fn new() -> Self {
+    pub fn new(config: &AppConfig) -> Self {
         let interfaces = pnet::datalink::interfaces()
             .into_iter()
             .filter(|iface| !iface.is_loopback() && !iface.ips.is_empty())
@@ -18,6 +28,11 @@ impl InterfaceSelector {
             interfaces,
             selected_interface: None,
             selected_interface_name: "Select an interface".to_string(),
+            replay_path: String::new(),
+            replay_requested: false,
+            record_capture: false,
+            capture_path: "ndm-capture.pcap".to_string(),
+            extra_ranges: config.admission_ranges.join(", "),
         }
     }
 
@@ -46,16 +61,64 @@ impl InterfaceSelector {
                         }
                     });
 
-                if ui.button("Select").clicked() {
-                    if self.selected_interface.is_some() {
-                        selection_made = true;
-                    }
+                ui.horizontal(|ui| {
+                    ui.label("Additional ranges (CIDR):");
+                    ui.text_edit_singleline(&mut self.extra_ranges);
+                });
+                if let Err(e) = parse_ranges(&self.extra_ranges) {
+                    ui.colored_label(egui::Color32::from_rgb(200, 50, 50), e.to_string());
+                }
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.record_capture, "Record discovery traffic to");
+                    ui.add_enabled(
+                        self.record_capture,
+                        egui::TextEdit::singleline(&mut self.capture_path),
+                    );
+                });
+
+                if ui.button("Select").clicked() && self.selected_interface.is_some() {
+                    selection_made = true;
                 }
+
+                ui.separator();
+                ui.label("Or rebuild the device list from a capture file (.pcap / .pcapng):");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.replay_path);
+                    if ui.button("Replay").clicked() && !self.replay_path.trim().is_empty() {
+                        self.replay_requested = true;
+                    }
+                });
             });
         selection_made
     }
 
+    pub fn interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
     pub fn get_selected_interface(&self) -> Option<NetworkInterface> {
         self.selected_interface.clone()
     }
+
+    // Ranges outside the interface's subnets whose hosts should still be listed
+    pub fn get_extra_ranges(&self) -> Vec<IpNetwork> {
+        parse_ranges(&self.extra_ranges).unwrap_or_default()
+    }
+
+    pub fn get_capture_config(&self) -> Option<CaptureConfig> {
+        if self.record_capture && !self.capture_path.trim().is_empty() {
+            Some(CaptureConfig::new(self.capture_path.trim()))
+        } else {
+            None
+        }
+    }
+
+    pub fn take_replay_path(&mut self) -> Option<PathBuf> {
+        if !self.replay_requested {
+            return None;
+        }
+        self.replay_requested = false;
+        Some(PathBuf::from(self.replay_path.trim()))
+    }
 }
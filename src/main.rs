@@ -1,14 +1,12 @@
-mod models;
-mod ui;
-mod scanner;
-mod restore;
-mod disconnect;
-mod privileges;
 mod interface_selector;
-mod killer;
+mod ui;
 
 use anyhow::Result;
+#[cfg(windows)]
+use ndm_core::privileges;
 use eframe::egui;
+use ndm_core::config::AppConfig;
+use ndm_core::logging;
 use once_cell::sync::Lazy;
 use tokio::runtime::Runtime;
 
@@ -16,6 +14,16 @@ pub static TOKIO_RUNTIME: Lazy<Runtime> =
     Lazy::new(|| Runtime::new().expect("Failed to create Tokio runtime"));
 
 fn run_app() -> Result<()> {
+    // A broken config is reported once the UI loads it again
+    let config = AppConfig::load().unwrap_or_default();
+    let _log_guard = match logging::init(&config.log_dir(), "ndm-gui", config.log_level()) {
+        Ok(guard) => Some(guard),
+        Err(e) => {
+            eprintln!("Logging to file is disabled: {:#}", e);
+            None
+        }
+    };
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1080.0, 650.0])
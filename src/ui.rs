@@ -1,68 +1,267 @@
-use crate::{
-    disconnect::kill_selected_devices,
-    interface_selector::InterfaceSelector,
-    killer::Killer,
-    models::{DeviceStatus, NetworkDevice},
-    restore::restore_selected_devices,
-    scanner::{NetworkScanner, ScanCommand},
-    TOKIO_RUNTIME,
+use crate::{interface_selector::InterfaceSelector, TOKIO_RUNTIME};
+use ndm_core::{
+    actions,
+    admission::AdmissionPolicy,
+    config::AppConfig,
+    diff::{Change, ScanDiff},
+    export::{self, ExportFormat},
+    import, inventory, logging,
+    models::{parse_tags, DeviceId, DeviceStatus, DeviceTable, NetworkDevice},
+    oui,
+    packet_io::{LivePacketIo, PacketIo},
+    profile::ProbeProfile,
+    resolver::ResolverConfig,
+    scanner::NetworkScanner,
+    session::shutdown_signal,
+    Event, EventBus, Session, SessionConfig, Subscription,
 };
+use chrono::Local;
 use dashmap::DashMap;
 use eframe::egui;
 use pnet::datalink::NetworkInterface;
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 use std::net::IpAddr;
+use tracing::{debug, info, warn};
+
+const INVENTORY_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_SCAN_DIFFS: usize = 20;
+// How long closing the window waits for blocked devices to be let back on
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct NetworkManagerApp {
-    devices: Arc<DashMap<IpAddr, NetworkDevice>>,
-    sorted_devices: Vec<IpAddr>,
+    devices: Arc<DeviceTable>,
+    sorted_devices: Vec<DeviceId>,
     auto_refresh: bool,
     last_scan: Instant,
     select_all: bool,
+    selected: HashSet<DeviceId>,
     interface_selector: InterfaceSelector,
-    selected_interface: Arc<Mutex<Option<NetworkInterface>>>,
-    device_receiver: mpsc::UnboundedReceiver<NetworkDevice>,
-    command_sender: Option<mpsc::UnboundedSender<ScanCommand>>,
+    session: Option<Session>,
+    packet_io: Arc<dyn PacketIo>,
+    events: EventBus,
+    subscription: Subscription,
     error: Arc<Mutex<Option<String>>>,
-    warning_receiver: mpsc::UnboundedReceiver<String>,
     proxy_arp_warning: Option<String>,
     last_device_count: usize,
+    replay_source: Option<String>,
+    profiles: Vec<ProbeProfile>,
+    selected_profile: usize,
+    resolver_config: ResolverConfig,
+    config: AppConfig,
+    // The current interface's inventory and the task saving it every so often
+    inventory_path: PathBuf,
+    inventory_saver: Option<JoinHandle<()>>,
+    search: String,
+    label_editor: Option<LabelEditor>,
+    export_dialog: Option<ExportDialog>,
+    import_dialog: Option<ImportDialog>,
+    // Newest first
+    scan_diffs: VecDeque<ScanDiff>,
+    show_changes: bool,
+    log_level: String,
+}
+
+struct ExportDialog {
+    path: String,
+    format: ExportFormat,
+    result: Option<Result<String, String>>,
+}
+
+struct ImportDialog {
+    path: String,
+    result: Option<Result<String, String>>,
+}
+
+// Edit buffer for the user-assigned name, tags and notes of one device
+struct LabelEditor {
+    id: DeviceId,
+    name: String,
+    tags: String,
+    notes: String,
 }
 
 impl NetworkManagerApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        let (_device_sender, device_receiver) = mpsc::unbounded_channel();
-        let (_warning_sender, warning_receiver) = mpsc::unbounded_channel();
-        let devices = Arc::new(DashMap::new());
-        let selected_interface = Arc::new(Mutex::new(None));
-        let killer = Killer::new(devices.clone(), selected_interface.clone());
-
-        let killer_clone = killer.clone();
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let config = AppConfig::load().unwrap_or_else(|e| {
+            warn!(error = %e, "Failed to load config, using defaults");
+            AppConfig::default()
+        });
+        let oui_path = config.oui_path();
+        if oui_path.exists() {
+            match oui::load_csv(&oui_path) {
+                Ok(count) => info!(count, path = %oui_path.display(), "Loaded vendor database"),
+                Err(e) => warn!(error = %e, "Failed to load vendor database"),
+            }
+        }
+        let profiles = config.profiles();
+        let default_profile = config.default_profile();
+        let selected_profile = profiles
+            .iter()
+            .position(|p| *p == default_profile)
+            .unwrap_or(0);
+        let events = EventBus::new();
+        let subscription = events.subscribe();
+
+        // Ctrl-C and SIGTERM close the window like the user would, so the session
+        // is shut down in on_exit either way
+        let ctx = cc.egui_ctx.clone();
         TOKIO_RUNTIME.spawn(async move {
-            killer_clone.start().await;
+            shutdown_signal().await;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            ctx.request_repaint();
         });
 
         Self {
-            devices,
+            devices: Arc::new(DashMap::new()),
             sorted_devices: Vec::new(),
             auto_refresh: false,
             last_scan: Instant::now(),
             select_all: false,
-            interface_selector: InterfaceSelector::new(),
-            selected_interface,
-            device_receiver,
-            command_sender: None,
+            selected: HashSet::new(),
+            interface_selector: InterfaceSelector::new(&config),
+            session: None,
+            packet_io: Arc::new(LivePacketIo),
+            events,
+            subscription,
             error: Arc::new(Mutex::new(None)),
-            warning_receiver,
             proxy_arp_warning: None,
             last_device_count: 0,
+            replay_source: None,
+            profiles,
+            selected_profile,
+            resolver_config: ResolverConfig {
+                dns_server: config.dns_server,
+                ..Default::default()
+            },
+            inventory_path: config.inventory_path(),
+            inventory_saver: None,
+            search: String::new(),
+            label_editor: None,
+            export_dialog: None,
+            import_dialog: None,
+            scan_diffs: VecDeque::new(),
+            show_changes: false,
+            log_level: logging::level().unwrap_or_else(|| config.log_level().to_string()),
+            config,
+        }
+    }
+
+    // Brings back the devices from earlier runs on `interface` and keeps the file up
+    // to date while scanning. Replays never touch the inventory.
+    fn load_inventory(&mut self, interface: &str) {
+        self.inventory_path = self.config.interface_inventory_path(interface);
+        match inventory::load_for_interface(&self.config, interface) {
+            Ok(devices) => {
+                for device in devices {
+                    self.devices.insert(device.id.clone(), device);
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to load inventory"),
+        }
+
+        let devices = self.devices.clone();
+        let path = self.inventory_path.clone();
+        self.inventory_saver = Some(TOKIO_RUNTIME.spawn(async move {
+            let mut interval = tokio::time::interval(INVENTORY_SAVE_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = inventory::save(&path, &devices) {
+                    warn!(error = %e, "Failed to save inventory");
+                }
+            }
+        }));
+    }
+
+    fn start_session(&mut self, interface: NetworkInterface) {
+        self.load_inventory(&interface.name);
+        let config = SessionConfig {
+            profile: self.profiles[self.selected_profile].clone(),
+            extra_ranges: self.interface_selector.get_extra_ranges(),
+            resolver: self.resolver_config.clone(),
+            capture: self.interface_selector.get_capture_config(),
+        };
+        let _runtime = TOKIO_RUNTIME.enter();
+        self.session = Some(Session::start(
+            interface,
+            self.packet_io.clone(),
+            self.devices.clone(),
+            self.events.clone(),
+            config,
+        ));
+    }
+
+    // Tears down the current session, saves its devices and starts over on
+    // `interface` with the devices saved for that one
+    fn switch_interface(&mut self, interface: NetworkInterface) {
+        info!(interface = %interface.name, "Switching interface");
+        self.stop_session();
+        if let Some(saver) = self.inventory_saver.take() {
+            saver.abort();
+        }
+        if let Err(e) = inventory::save(&self.inventory_path, &self.devices) {
+            warn!(error = %e, "Failed to save inventory");
+        }
+        // Whatever the old session published is about devices we no longer show
+        while self.subscription.try_recv().is_some() {}
+
+        self.devices = Arc::new(DashMap::new());
+        self.sorted_devices.clear();
+        self.selected.clear();
+        self.select_all = false;
+        self.last_device_count = 0;
+        self.scan_diffs.clear();
+        self.proxy_arp_warning = None;
+        self.label_editor = None;
+        *self.error.lock().unwrap() = None;
+        self.start_session(interface);
+    }
+
+    // Picks another interface to scan. Returns the one chosen, if it differs from
+    // the current one.
+    fn render_interface_switcher(&self, ui: &mut egui::Ui) -> Option<NetworkInterface> {
+        let current = self.session.as_ref()?.interface();
+        let label = |iface: &NetworkInterface| {
+            if iface.description.is_empty() {
+                iface.name.clone()
+            } else {
+                iface.description.clone()
+            }
+        };
+        let mut chosen = None;
+        egui::ComboBox::from_id_source("interface_switcher")
+            .selected_text(format!("Interface: {}", label(current)))
+            .show_ui(ui, |ui| {
+                for iface in self.interface_selector.interfaces() {
+                    let is_current = iface.name == current.name;
+                    if ui.selectable_label(is_current, label(iface)).clicked() && !is_current {
+                        chosen = Some(iface.clone());
+                    }
+                }
+            });
+        chosen
+    }
+
+    // Stops the scanner and killer, which lets blocked devices back on. Blocks
+    // the UI until then, within SHUTDOWN_TIMEOUT.
+    fn stop_session(&mut self) {
+        if let Some(session) = self.session.take() {
+            let stopped = TOKIO_RUNTIME.block_on(async {
+                tokio::time::timeout(SHUTDOWN_TIMEOUT, session.shutdown()).await
+            });
+            if stopped.is_err() {
+                warn!("Session did not shut down in time");
+            }
         }
     }
 
     fn render_header(&mut self, ui: &mut egui::Ui) {
+        let mut switch_to = None;
         ui.horizontal(|ui| {
             ui.add_space(10.0);
             ui.heading("Network Device Manager");
@@ -70,19 +269,51 @@ impl NetworkManagerApp {
             ui.label("Monitor and manage network devices");
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                 ui.add_space(10.0);
-                ui.colored_label(egui::Color32::GREEN, "● Connected");
+                match &self.replay_source {
+                    Some(source) => ui.colored_label(
+                        egui::Color32::from_rgb(100, 100, 100),
+                        format!("● Replay: {}", source),
+                    ),
+                    None => ui.colored_label(egui::Color32::GREEN, "● Connected"),
+                };
                 ui.add_space(10.0);
-                if ui.checkbox(&mut self.auto_refresh, "Auto-refresh").clicked() {
-                    if self.auto_refresh {
-                        self.last_scan = Instant::now();
-                    }
+                self.render_log_level(ui);
+                ui.add_space(10.0);
+                switch_to = self.render_interface_switcher(ui);
+                ui.add_space(10.0);
+                if ui.checkbox(&mut self.auto_refresh, "Auto-refresh").clicked()
+                    && self.auto_refresh
+                {
+                    self.last_scan = Instant::now();
                 }
             });
         });
+        if let Some(interface) = switch_to {
+            self.switch_interface(interface);
+        }
         ui.add_space(5.0);
         ui.separator();
     }
 
+    // Changes what goes to the log file while the app runs, e.g. to catch a problem
+    // on a customer machine at debug level
+    fn render_log_level(&mut self, ui: &mut egui::Ui) {
+        let previous = self.log_level.clone();
+        egui::ComboBox::from_id_source("log_level")
+            .selected_text(format!("Log: {}", self.log_level))
+            .show_ui(ui, |ui| {
+                for level in ["error", "warn", "info", "debug", "trace"] {
+                    ui.selectable_value(&mut self.log_level, level.to_string(), level);
+                }
+            });
+        if self.log_level != previous {
+            if let Err(e) = logging::set_level(&self.log_level) {
+                warn!(error = %e, "Failed to change log level");
+                self.log_level = previous;
+            }
+        }
+    }
+
     fn render_info_panel(&self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.add_space(5.0);
@@ -128,10 +359,8 @@ impl NetworkManagerApp {
                 )
                 .clicked()
             {
-                println!("[UI] Scan button clicked");
-                if let Some(sender) = &self.command_sender {
-                    let _ = sender.send(ScanCommand::Scan);
-                }
+                debug!("Scan button clicked");
+                self.send_scan();
             }
             ui.add_space(5.0);
             if ui
@@ -142,12 +371,69 @@ impl NetworkManagerApp {
                 )
                 .clicked()
             {
-                if let Some(sender) = &self.command_sender {
-                    let _ = sender.send(ScanCommand::Scan);
-                }
+                self.send_scan();
+            }
+            ui.add_space(5.0);
+            egui::ComboBox::from_id_source("probe_profile")
+                .selected_text(&self.profiles[self.selected_profile].name)
+                .show_ui(ui, |ui| {
+                    for (idx, profile) in self.profiles.iter().enumerate() {
+                        ui.selectable_value(&mut self.selected_profile, idx, &profile.name);
+                    }
+                });
+            ui.add_space(5.0);
+            if ui
+                .add_sized(
+                    [110.0, 35.0],
+                    egui::Button::new(egui::RichText::new("📄 Export").color(egui::Color32::BLACK))
+                        .fill(egui::Color32::from_rgb(230, 230, 230)),
+                )
+                .clicked()
+            {
+                self.export_dialog = Some(ExportDialog {
+                    path: "ndm-report.html".to_string(),
+                    format: ExportFormat::Html,
+                    result: None,
+                });
+            }
+            ui.add_space(5.0);
+            if ui
+                .add_sized(
+                    [110.0, 35.0],
+                    egui::Button::new(egui::RichText::new("📥 Import").color(egui::Color32::BLACK))
+                        .fill(egui::Color32::from_rgb(230, 230, 230)),
+                )
+                .clicked()
+            {
+                self.import_dialog = Some(ImportDialog {
+                    path: String::new(),
+                    result: None,
+                });
+            }
+            ui.add_space(5.0);
+            let pending = self.scan_diffs.front().map_or(0, |diff| diff.changes.len());
+            let changes_text = if pending > 0 {
+                format!("🕘 Changes ({})", pending)
+            } else {
+                "🕘 Changes".to_string()
+            };
+            if ui
+                .add_sized(
+                    [120.0, 35.0],
+                    egui::Button::new(egui::RichText::new(changes_text).color(egui::Color32::BLACK))
+                        .fill(egui::Color32::from_rgb(230, 230, 230)),
+                )
+                .clicked()
+            {
+                self.show_changes = !self.show_changes;
             }
             ui.add_space(20.0);
-            let selected_count = self.devices.iter().filter(|d| d.selected).count();
+            // Selected rows can vanish when a device is re-keyed
+            let selected_count = self
+                .selected
+                .iter()
+                .filter(|id| self.devices.contains_key(*id))
+                .count();
             self.render_disconnect_button(ui, selected_count);
             ui.add_space(5.0);
             self.render_restore_button(ui, selected_count);
@@ -158,6 +444,12 @@ impl NetworkManagerApp {
         });
     }
 
+    fn send_scan(&self) {
+        if let Some(session) = &self.session {
+            session.scan(self.profiles[self.selected_profile].clone());
+        }
+    }
+
     fn render_disconnect_button(&mut self, ui: &mut egui::Ui, selected_count: usize) {
         if ui
             .add_sized(
@@ -170,7 +462,8 @@ impl NetworkManagerApp {
             )
             .clicked()
         {
-            kill_selected_devices(&self.devices);
+            let ids: Vec<DeviceId> = self.selected.iter().cloned().collect();
+            actions::block(&self.devices, &ids);
         }
     }
 
@@ -186,7 +479,8 @@ impl NetworkManagerApp {
             )
             .clicked()
         {
-            restore_selected_devices(&self.devices);
+            let ids: Vec<DeviceId> = self.selected.iter().cloned().collect();
+            actions::restore(&self.devices, &ids);
         }
     }
 
@@ -201,9 +495,7 @@ impl NetworkManagerApp {
             )
             .clicked()
         {
-            for mut device in self.devices.iter_mut() {
-                device.is_killed = false;
-            }
+            actions::restore_all(&self.devices);
         }
     }
 
@@ -218,18 +510,28 @@ impl NetworkManagerApp {
             )
             .clicked()
         {
-            for mut device in self.devices.iter_mut() {
-                device.is_killed = true;
-            }
+            actions::block_all(&self.devices);
         }
     }
 
     fn render_device_table(&mut self, ui: &mut egui::Ui) {
-        ui.label(
-            egui::RichText::new(format!("Network Devices ({})", self.devices.len()))
-                .size(16.0)
-                .strong(),
-        );
+        ui.horizontal(|ui| {
+            ui.label(
+                egui::RichText::new(format!("Network Devices ({})", self.devices.len()))
+                    .size(16.0)
+                    .strong(),
+            );
+            ui.add_space(20.0);
+            ui.label("🔎");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.search)
+                    .hint_text("Search names, tags, notes, addresses")
+                    .desired_width(260.0),
+            );
+            if !self.search.is_empty() && ui.button("✖").clicked() {
+                self.search.clear();
+            }
+        });
         ui.add_space(5.0);
         self.render_table_header(ui);
         ui.separator();
@@ -243,57 +545,357 @@ impl NetworkManagerApp {
                 ui.horizontal(|ui| {
                     ui.add_space(10.0);
                     if ui.checkbox(&mut self.select_all, "Select").changed() {
-                        for mut device in self.devices.iter_mut() {
-                            device.selected = self.select_all;
-                        }
+                        self.selected = if self.select_all {
+                            self.devices.iter().map(|d| d.key().clone()).collect()
+                        } else {
+                            HashSet::new()
+                        };
                     }
                     ui.add_space(40.0);
                     ui.label(egui::RichText::new("IP Address").strong().size(12.0));
                     ui.add_space(90.0);
-                    ui.label(egui::RichText::new("Hostname").strong().size(12.0));
+                    ui.label(egui::RichText::new("Name").strong().size(12.0));
                     ui.add_space(100.0);
                     ui.label(egui::RichText::new("MAC Address").strong().size(12.0));
                     ui.add_space(60.0);
                     ui.label(egui::RichText::new("Vendor").strong().size(12.0));
                     ui.add_space(80.0);
+                    ui.label(egui::RichText::new("Open Ports").strong().size(12.0));
+                    ui.add_space(60.0);
                     ui.label(egui::RichText::new("Status").strong().size(12.0));
                 });
             });
     }
 
     fn render_table_content(&mut self, ui: &mut egui::Ui) {
+        let mut edit_request = None;
+        let mut toggled = Vec::new();
         egui::ScrollArea::vertical()
             .max_height(400.0)
             .show(ui, |ui| {
-                for (idx, ip) in self.sorted_devices.iter().enumerate() {
-                    if let Some(mut device) = self.devices.get_mut(ip) {
+                let mut idx = 0;
+                for id in self.sorted_devices.iter() {
+                    if let Some(device) = self.devices.get(id) {
+                        if !device.matches(&self.search) {
+                            continue;
+                        }
                         let bg_color = if idx % 2 == 0 {
                             egui::Color32::from_rgb(255, 255, 255)
                         } else {
                             egui::Color32::from_rgb(250, 250, 250)
                         };
+                        idx += 1;
                         egui::Frame::none().fill(bg_color).show(ui, |ui| {
-                            self.render_device_row(ui, &mut device);
+                            let mut selected = self.selected.contains(id);
+                            if self.render_device_row(ui, &device, &mut selected) {
+                                edit_request = Some(LabelEditor {
+                                    id: id.clone(),
+                                    name: device.labels.name.clone(),
+                                    tags: device.labels.tags.join(", "),
+                                    notes: device.labels.notes.clone(),
+                                });
+                            }
+                            if selected != self.selected.contains(id) {
+                                toggled.push(id.clone());
+                            }
                         });
                         ui.add_space(2.0);
                     }
                 }
             });
+        for id in toggled {
+            if !self.selected.remove(&id) {
+                self.selected.insert(id);
+            }
+        }
+        if edit_request.is_some() {
+            self.label_editor = edit_request;
+        }
+    }
+
+    fn render_export_dialog(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = self.export_dialog.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Export devices")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Format:");
+                    let previous = dialog.format;
+                    egui::ComboBox::from_id_source("export_format")
+                        .selected_text(dialog.format.extension().to_uppercase())
+                        .show_ui(ui, |ui| {
+                            for format in ExportFormat::ALL {
+                                ui.selectable_value(
+                                    &mut dialog.format,
+                                    format,
+                                    format.extension().to_uppercase(),
+                                );
+                            }
+                        });
+                    if dialog.format != previous {
+                        dialog.path = PathBuf::from(&dialog.path)
+                            .with_extension(dialog.format.extension())
+                            .display()
+                            .to_string();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.add(egui::TextEdit::singleline(&mut dialog.path).desired_width(280.0));
+                });
+                ui.add_space(5.0);
+                if ui.button("Export").clicked() {
+                    let path = PathBuf::from(dialog.path.trim());
+                    // A typed extension wins over the combo box
+                    if let Some(format) = ExportFormat::from_path(&path) {
+                        dialog.format = format;
+                    }
+                    dialog.result = Some(
+                        export::export_to_file(&self.devices, dialog.format, &path)
+                            .map(|()| format!("Wrote {}", path.display()))
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+                match &dialog.result {
+                    Some(Ok(message)) => {
+                        ui.colored_label(egui::Color32::from_rgb(50, 150, 50), message);
+                    }
+                    Some(Err(error)) => {
+                        ui.colored_label(egui::Color32::from_rgb(200, 50, 50), error);
+                    }
+                    None => {}
+                }
+            });
+        if !open {
+            self.export_dialog = None;
+        }
+    }
+
+    fn render_changes(&mut self, ctx: &egui::Context) {
+        if !self.show_changes {
+            return;
+        }
+        let mut open = true;
+        egui::Window::new("Changes since previous scan")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                if self.scan_diffs.is_empty() {
+                    ui.label("No scan has been compared yet. Run a scan to see what changed.");
+                    return;
+                }
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    for diff in &self.scan_diffs {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{} → {}",
+                                diff.previous_at.with_timezone(&Local).format("%H:%M:%S"),
+                                diff.current_at.with_timezone(&Local).format("%H:%M:%S"),
+                            ))
+                            .strong(),
+                        );
+                        if diff.is_empty() {
+                            ui.label(egui::RichText::new("No changes").color(egui::Color32::GRAY));
+                        }
+                        for change in &diff.changes {
+                            let color = match change.change {
+                                Change::NewDevice | Change::PortOpened { .. } => {
+                                    egui::Color32::from_rgb(200, 120, 0)
+                                }
+                                Change::DeviceGone | Change::PortClosed { .. } => {
+                                    egui::Color32::from_rgb(100, 100, 100)
+                                }
+                                Change::IpChanged { .. } | Change::MacChanged { .. } => {
+                                    egui::Color32::from_rgb(0, 120, 215)
+                                }
+                            };
+                            ui.colored_label(color, change.to_string());
+                        }
+                        ui.separator();
+                    }
+                });
+            });
+        if !open {
+            self.show_changes = false;
+        }
+    }
+
+    fn render_import_dialog(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = self.import_dialog.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        let mut imported = false;
+        egui::Window::new("Import devices")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Asset CSV, dnsmasq or ISC DHCP leases, or nmap -oX output");
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut dialog.path)
+                            .hint_text("dnsmasq.leases")
+                            .desired_width(280.0),
+                    );
+                });
+                ui.add_space(5.0);
+                if ui.button("Import").clicked() {
+                    let path = PathBuf::from(dialog.path.trim());
+                    dialog.result = Some(
+                        import::import_file(&self.devices, &path)
+                            .map(|summary| {
+                                format!(
+                                    "{} added, {} updated, {} skipped",
+                                    summary.added, summary.updated, summary.skipped
+                                )
+                            })
+                            .map_err(|e| e.to_string()),
+                    );
+                    imported = matches!(dialog.result, Some(Ok(_)));
+                }
+                match &dialog.result {
+                    Some(Ok(message)) => {
+                        ui.colored_label(egui::Color32::from_rgb(50, 150, 50), message);
+                    }
+                    Some(Err(error)) => {
+                        ui.colored_label(egui::Color32::from_rgb(200, 50, 50), error);
+                    }
+                    None => {}
+                }
+            });
+        if !open {
+            self.import_dialog = None;
+        }
+        if imported {
+            // Merges can move devices to a new address without changing the count
+            self.last_device_count = usize::MAX;
+        }
     }
 
-    fn render_device_row(&self, ui: &mut egui::Ui, device: &mut NetworkDevice) {
+    fn render_label_editor(&mut self, ctx: &egui::Context) {
+        let Some(editor) = self.label_editor.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        let mut done = false;
+        let mut saved = false;
+        egui::Window::new("Edit device")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("label_editor").num_columns(2).show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut editor.name);
+                    ui.end_row();
+                    ui.label("Tags:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut editor.tags)
+                            .hint_text("printer, guest, CCTV"),
+                    );
+                    ui.end_row();
+                    ui.label("Notes:");
+                    ui.text_edit_multiline(&mut editor.notes);
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        if let Some(mut device) = self.devices.get_mut(&editor.id) {
+                            device.labels.name = editor.name.trim().to_string();
+                            device.labels.tags = parse_tags(&editor.tags);
+                            device.labels.notes = editor.notes.trim().to_string();
+                        }
+                        done = true;
+                        saved = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        done = true;
+                    }
+                });
+            });
+        if done || !open {
+            self.label_editor = None;
+        }
+        // Labels are hand-entered, so write them out now rather than at the next autosave
+        if saved && self.replay_source.is_none() {
+            if let Err(e) = inventory::save(&self.inventory_path, &self.devices) {
+                warn!(error = %e, "Failed to save inventory");
+            }
+        }
+    }
+
+    fn address_details(device: &NetworkDevice) -> String {
+        let mut text = String::new();
+        if !device.ipv6_addresses.is_empty() {
+            text.push_str(&format!("IPv6:\n{}\n\n", device.ipv6_addresses.join("\n")));
+        }
+        text.push_str("Address history:");
+        for record in device.ip_history.iter().rev() {
+            text.push_str(&format!(
+                "\n{}  {} – {}",
+                record.address,
+                record.first_seen.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                record.last_seen.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            ));
+        }
+        text
+    }
+
+    // Returns true when the user asked to edit the device's labels
+    fn render_device_row(
+        &self,
+        ui: &mut egui::Ui,
+        device: &NetworkDevice,
+        selected: &mut bool,
+    ) -> bool {
+        let mut edit = false;
         ui.horizontal(|ui| {
             ui.add_space(10.0);
-            ui.checkbox(&mut device.selected, "");
+            ui.checkbox(selected, "");
             ui.add_space(30.0);
-            ui.label(egui::RichText::new(&device.ip_address).size(12.0));
+            let ip_label = ui.label(egui::RichText::new(&device.ip_address).size(12.0));
+            if device.ip_history.len() > 1 || !device.ipv6_addresses.is_empty() {
+                ip_label.on_hover_text(Self::address_details(device));
+            }
             ui.add_space(70.0);
-            ui.label(egui::RichText::new(&device.hostname).size(12.0));
+            let name_label = ui.label(egui::RichText::new(device.display_name()).size(12.0));
+            let mut details = Vec::new();
+            if !device.labels.name.is_empty() && !device.hostname.is_empty() {
+                details.push(format!("Hostname: {}", device.hostname));
+            }
+            for (field, source) in &device.field_sources {
+                details.push(format!("{} from {}", field, source));
+            }
+            if !details.is_empty() {
+                name_label.on_hover_text(details.join("\n"));
+            }
+            if !device.labels.tags.is_empty() {
+                let tags = ui.label(
+                    egui::RichText::new(device.labels.tags.join(", "))
+                        .size(10.0)
+                        .color(egui::Color32::from_rgb(0, 120, 215)),
+                );
+                if !device.labels.notes.is_empty() {
+                    tags.on_hover_text(&device.labels.notes);
+                }
+            }
+            if ui.small_button("✏").on_hover_text("Name, tags and notes").clicked() {
+                edit = true;
+            }
             ui.add_space(50.0);
             ui.label(egui::RichText::new(&device.mac_address).size(12.0));
             ui.add_space(50.0);
             ui.label(egui::RichText::new(&device.vendor).size(12.0));
             ui.add_space(70.0);
+            self.render_ports(ui, device);
+            ui.add_space(50.0);
             let (status_text, status_color) = if device.is_killed {
                 ("Blocked", egui::Color32::from_rgb(200, 50, 50))
             } else {
@@ -303,14 +905,40 @@ impl NetworkManagerApp {
                     _ => ("Unknown", egui::Color32::from_rgb(150, 150, 150)),
                 }
             };
-            ui.colored_label(status_color, status_text);
+            let status_label = ui.colored_label(status_color, status_text);
+            if device.off_link_frames > 0 {
+                status_label.on_hover_text(format!(
+                    "Routed {} frames from off-link hosts",
+                    device.off_link_frames
+                ));
+            }
         });
+        edit
     }
-    fn render_warnings(&mut self, ui: &mut egui::Ui) {
-        if let Ok(warning) = self.warning_receiver.try_recv() {
-            self.proxy_arp_warning = Some(warning);
+    fn render_ports(&self, ui: &mut egui::Ui, device: &NetworkDevice) {
+        let open_ports = device.open_ports();
+        let summary = if open_ports.is_empty() {
+            "-".to_string()
+        } else {
+            open_ports
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let label = ui.label(egui::RichText::new(summary).size(12.0));
+        if !device.ports.is_empty() {
+            let details = device
+                .ports
+                .iter()
+                .map(|(port, state)| format!("{}/tcp  {:?}", port, state))
+                .collect::<Vec<_>>()
+                .join("\n");
+            label.on_hover_text(details);
         }
+    }
 
+    fn render_warnings(&mut self, ui: &mut egui::Ui) {
         if let Some(warning) = &self.proxy_arp_warning {
             ui.add_space(10.0);
             egui::Frame::none()
@@ -329,55 +957,90 @@ impl NetworkManagerApp {
 }
 
 impl eframe::App for NetworkManagerApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        let live = self.replay_source.is_none() && self.session.is_some();
+        self.stop_session();
+        if let Some(saver) = self.inventory_saver.take() {
+            saver.abort();
+        }
+        if live {
+            if let Err(e) = inventory::save(&self.inventory_path, &self.devices) {
+                warn!(error = %e, "Failed to save inventory");
+            }
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        while let Ok(device) = self.device_receiver.try_recv() {
-            if let Ok(ip) = device.ip_address.parse() {
-                self.devices.insert(ip, device);
+        // The scanner writes into the shared table itself and only tells us about new
+        // devices and address changes, which can reorder rows without changing the count
+        let mut arrived = false;
+        while let Some(event) = self.subscription.try_recv() {
+            match event {
+                Event::DeviceDiscovered { .. } | Event::DeviceUpdated { .. } => arrived = true,
+                Event::WarningRaised { message } => self.proxy_arp_warning = Some(message),
+                Event::ScanFinished {
+                    diff: Some(diff), ..
+                } => {
+                    self.scan_diffs.push_front(diff);
+                    self.scan_diffs.truncate(MAX_SCAN_DIFFS);
+                }
+                _ => {}
             }
         }
+        // The bus drops the oldest events while we're not drawn, e.g. minimized,
+        // and those may have been address changes
+        if self.subscription.take_missed() > 0 {
+            arrived = true;
+        }
 
         let current_device_count = self.devices.len();
-        if current_device_count != self.last_device_count {
-            self.sorted_devices = self.devices.iter().map(|d| *d.key()).collect();
-            self.sorted_devices.sort();
+        if arrived || current_device_count != self.last_device_count {
+            let mut rows: Vec<(Option<IpAddr>, DeviceId)> = self
+                .devices
+                .iter()
+                .map(|d| (d.ip_address.parse().ok(), d.key().clone()))
+                .collect();
+            rows.sort();
+            self.sorted_devices = rows.into_iter().map(|(_, id)| id).collect();
             self.last_device_count = current_device_count;
         }
 
-        if self.selected_interface.lock().unwrap().is_none() {
-            if self.interface_selector.show(ctx) {
+        if self.session.is_none() && self.replay_source.is_none() {
+            if let Some(path) = self.interface_selector.take_replay_path() {
+                self.replay_source = Some(path.display().to_string());
+                let admission = AdmissionPolicy::new(self.interface_selector.get_extra_ranges());
+                let devices = self.devices.clone();
+                let events = self.events.clone();
+                let error_clone = self.error.clone();
+                TOKIO_RUNTIME.spawn(async move {
+                    if let Err(e) =
+                        NetworkScanner::replay_capture(&path, admission, devices, events).await
+                    {
+                        *error_clone.lock().unwrap() = Some(e.to_string());
+                    }
+                });
+            } else if self.interface_selector.show(ctx) {
                 if let Some(interface) = self.interface_selector.get_selected_interface() {
-                    *self.selected_interface.lock().unwrap() = Some(interface.clone());
-                    let (device_sender, device_receiver) = mpsc::unbounded_channel();
-                    let (command_sender, command_receiver) = mpsc::unbounded_channel();
-                    let (warning_sender, warning_receiver) = mpsc::unbounded_channel();
-                    self.device_receiver = device_receiver;
-                    self.command_sender = Some(command_sender);
-                    self.warning_receiver = warning_receiver;
-                    let mut scanner = NetworkScanner::new(
-                        interface.clone(),
-                        self.devices.clone(),
-                        device_sender,
-                        command_receiver,
-                        warning_sender,
-                    );
-                    let error_clone = self.error.clone();
-                    TOKIO_RUNTIME.spawn(async move {
-                        if let Err(e) = scanner.start().await {
-                            *error_clone.lock().unwrap() = Some(e.to_string());
-                        }
-                    });
+                    self.start_session(interface);
                 }
             }
         } else {
-            if let Some(error) = self.error.lock().unwrap().as_ref() {
+            let error = self.error.lock().unwrap().clone();
+            if let Some(error) = error.or_else(|| self.session.as_ref().and_then(Session::error)) {
+                let mut switch_to = None;
                 egui::CentralPanel::default().show(ctx, |ui| {
                     ui.vertical_centered(|ui| {
                         ui.add_space(100.0);
                         ui.heading("Error");
                         ui.add_space(20.0);
                         ui.label(error);
+                        ui.add_space(20.0);
+                        switch_to = self.render_interface_switcher(ui);
                     });
                 });
+                if let Some(interface) = switch_to {
+                    self.switch_interface(interface);
+                }
                 return;
             }
             egui::CentralPanel::default().show(ctx, |ui| {
@@ -393,6 +1056,10 @@ impl eframe::App for NetworkManagerApp {
                 ui.add_space(1.0);
                 self.render_device_table(ui);
             });
+            self.render_label_editor(ctx);
+            self.render_export_dialog(ctx);
+            self.render_import_dialog(ctx);
+            self.render_changes(ctx);
         }
         ctx.request_repaint_after(Duration::from_millis(100));
     }